@@ -48,13 +48,14 @@ fn key_pressed(app: &App, model: &mut Model, key: Key) {
         Key::Space => {
             model.reset(w, h);
         }
+        Key::P => {
+            model.toggle_packing().generate_particles(w, h);
+        }
         Key::Up => {
             model.num(model.num + 5).generate_particles(w, h);
         }
-        Key::Down => {
-            if model.num > 1 {
-                model.num(model.num - 5).generate_particles(w, h);
-            }
+        Key::Down if model.num > 1 => {
+            model.num(model.num - 5).generate_particles(w, h);
         }
         _ => (),
     }
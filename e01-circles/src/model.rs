@@ -1,24 +1,31 @@
-use crate::particles::Particle;
+use crate::particles::Circle;
 use crate::random_range;
 
+use lib::common::Seedable;
+use lib::packing::{Packer, Radii};
+use lib::particles::system::ParticleSystem;
 use lib::utils::rng;
+use nannou::glam::vec2;
 use nannou::rand::Rng;
 
 pub struct Model {
     pub hue: f32,
     pub num: u32,
     pub seed: u64,
-    particles: Vec<Particle>,
+    // non-overlapping circles instead of random ones
+    pub packed: bool,
+    particles: ParticleSystem<Circle>,
 }
 
 impl Model {
     pub fn new() -> Self {
-        let particles = Vec::new();
+        let particles = ParticleSystem::new(1.0 / 60.0);
 
         Model {
             hue: 0.0,
             seed: 0,
             num: 1,
+            packed: false,
             particles,
         }
     }
@@ -42,9 +49,14 @@ impl Model {
         self
     }
 
+    pub fn toggle_packing(&mut self) -> &mut Self {
+        self.packed = !self.packed;
+        self
+    }
+
     pub fn display(&self, draw: &nannou::Draw) {
-        for particle in &self.particles {
-            particle.display(&draw);
+        for particle in self.particles.particles() {
+            Circle::display(particle, draw);
         }
     }
 
@@ -60,17 +72,37 @@ impl Model {
 
     pub fn generate_particles(&mut self, w: f32, h: f32) -> &mut Self {
         let mut rng = rng(self.seed);
+        let (min, max) = (vec2(-w, -h) / 2.0, vec2(w, h) / 2.0);
 
-        self.particles = Vec::new();
+        self.particles.clear();
+
+        if self.packed {
+            let radii = Radii::Exp {
+                k: 20.0,
+                min: 2.0,
+                max: 256.0,
+                favor_min: true,
+            };
+            let circles = Packer::new(min, max, radii)
+                .gap(2.0)
+                .set_seed(self.seed as u32)
+                .grow(self.num as usize);
+            for circle in circles {
+                let (x, y) = (circle.center.x, circle.center.y);
+                let particle = Circle::new(x, y, circle.radius, self.hue, &mut rng);
+                self.particles.spawn(particle);
+            }
+            return self;
+        }
 
         for _ in 0..self.num {
-            let particle = Particle::random(
+            let particle = Circle::random(
                 rng.gen_range(-0.5..0.5) * w,
                 rng.gen_range(-0.5..0.5) * h,
                 self.hue,
                 &mut rng,
             );
-            self.particles.push(particle);
+            self.particles.spawn(particle);
         }
 
         self
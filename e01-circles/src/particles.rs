@@ -1,35 +1,42 @@
 use lib::colors::Color;
+use lib::particles::particle::Particle;
 use lib::utils::exp_rng;
 use nannou::color::Rgba;
+use nannou::prelude::vec2;
 use nannou::rand::rngs::StdRng;
 use nannou::rand::Rng;
 
-pub struct Particle {
-    x: f32,
-    y: f32,
+// what a circle adds to a plain particle
+pub struct Circle {
     radius: f32,
     color: Rgba,
 }
 
-impl Particle {
-    pub fn random(x: f32, y: f32, hue: f32, rng: &mut StdRng) -> Self {
-        let l = rng.gen_range(0.70..0.95);
-        let c = rng.gen_range(0.15..0.35);
-        let color = Color::new(l, c, hue, 1.0).into();
+impl Circle {
+    pub fn random(x: f32, y: f32, hue: f32, rng: &mut StdRng) -> Particle<Circle> {
+        let color = shade(hue, rng);
         let radius = exp_rng(rng, 20.0, 2.0, 256.0, true);
 
-        Particle {
-            x,
-            y,
-            color,
-            radius,
-        }
+        Particle::with_data(vec2(x, y), Circle { radius, color })
     }
 
-    pub fn display(&self, draw: &nannou::Draw) {
+    // a circle with a given radius and a random shade of the hue
+    pub fn new(x: f32, y: f32, radius: f32, hue: f32, rng: &mut StdRng) -> Particle<Circle> {
+        let color = shade(hue, rng);
+        Particle::with_data(vec2(x, y), Circle { radius, color })
+    }
+
+    pub fn display(particle: &Particle<Circle>, draw: &nannou::Draw) {
         draw.ellipse()
-            .x_y(self.x, self.y)
-            .radius(self.radius)
-            .color(self.color);
+            .xy(particle.position)
+            .radius(particle.data.radius)
+            .color(particle.data.color);
     }
 }
+
+// lightness then chroma, drawn in the order existing seeds and screenshots rely on
+fn shade(hue: f32, rng: &mut StdRng) -> Rgba {
+    let l = rng.gen_range(0.70..0.95);
+    let c = rng.gen_range(0.15..0.35);
+    Color::new(l, c, hue, 1.0).into()
+}
@@ -1,9 +1,25 @@
-use lib::{common::Seedable, forces::field::*, forces::map::FlowField};
+use lib::canvas::{Blend, Canvas, ToneMap};
+use lib::particles::{
+    emitter::{Emitter, Shape},
+    particle::Particle,
+    steering::{Behavior, Steering},
+    system::*,
+};
+use lib::{
+    common::Seedable, forces::analysis::CriticalPoint, forces::evenly::EvenlySpaced,
+    forces::expr::ExprField, forces::field::*, forces::map::FlowField, forces::mask::*,
+    forces::render::DisplayMode, forces::scalar::ScalarField, forces::streamline::Tracer,
+};
 use nannou::prelude::*;
+use std::fs;
 
 const WIDTH: u32 = 800;
 const HEIGHT: u32 = 800;
 const MARGIN: f32 = 0.0;
+const TIME_SPEED: f64 = 0.2;
+const PARTICLES: usize = 1500;
+// field vectors are around unit length, this turns them into pixels per second²
+const PARTICLE_FORCE: f32 = 400.0;
 
 fn main() {
     nannou::app(model)
@@ -16,7 +32,31 @@ struct Model {
     seed: u32,
     width: f32,
     height: f32,
+    time: f64,
+    animate: bool,
+    // perlin only inside a disc at the center and the attractors outside it,
+    // instead of all three over the whole field
+    masked: bool,
+    mode: DisplayMode,
+    // divergence and critical points drawn under/over the field while on,
+    // computed whenever the field changes rather than every frame
+    analysis: Option<(ScalarField, Vec<CriticalPoint>)>,
+    // evenly spaced streamlines over the visible part of the field while on,
+    // placed again whenever the field changes
+    streamlines: Option<Vec<Vec<Vec2>>>,
     flowfield: FlowField,
+    // particles pushed around by the field
+    particles: ParticleSystem,
+    show_particles: bool,
+    // particles flock along the field as boids instead of being pushed by it
+    boids: bool,
+    steering: Steering,
+    // particle paths accumulated into a long exposure while it is on
+    exposure: Option<Canvas>,
+    // optional file with an expression field, e.g. `angle = noise(x * 0.05, y * 0.05) * tau`
+    // passed as the first argument: `cargo run -p e02-flowlines -- field.txt`
+    field_file: Option<String>,
+    expr: Option<ExprField>,
 }
 
 impl Seedable for Model {
@@ -27,6 +67,10 @@ impl Seedable for Model {
     fn set_seed(&mut self, seed: u32) -> &mut Self {
         self.seed = seed;
         self.flowfield.set_seed(seed);
+        self.steering.set_seed(seed);
+        if let Some(expr) = &mut self.expr {
+            expr.set_seed(seed);
+        }
         self
     }
 }
@@ -37,14 +81,52 @@ impl Model {
         let mut flowfield = FlowField::new(width, height, 20.0, 1.0);
         flowfield.set_seed(seed);
 
+        let mut steering = Steering::new(120.0, 300.0);
+        steering
+            .add(Behavior::Separation { radius: 8.0 }, 1.5)
+            .add(Behavior::Alignment { radius: 24.0 }, 1.0)
+            .add(Behavior::Cohesion { radius: 24.0 }, 0.8)
+            .add(Behavior::Follow, 1.0)
+            .set_seed(seed);
+
         Model {
             seed,
             width,
             height,
+            time: 0.0,
+            animate: false,
+            masked: false,
+            mode: DisplayMode::Lines,
+            analysis: None,
+            streamlines: None,
             flowfield,
+            particles: ParticleSystem::new(1.0 / 60.0),
+            show_particles: false,
+            boids: false,
+            steering,
+            exposure: None,
+            field_file: std::env::args().nth(1),
+            expr: None,
         }
     }
 
+    // (re)load the expression field file, keeping the previous field on errors
+    fn load_field(&mut self) -> &mut Self {
+        if let Some(path) = &self.field_file {
+            let field = fs::read_to_string(path)
+                .map_err(|err| err.to_string())
+                .and_then(|src| {
+                    ExprField::parse(&src, 1.0, self.seed).map_err(|err| err.to_string())
+                });
+
+            match field {
+                Ok(field) => self.expr = Some(field),
+                Err(err) => eprintln!("{}: {}", path, err),
+            }
+        }
+        self
+    }
+
     fn reset_seed(&mut self) -> &mut Self {
         self.set_seed(random_range(0, 1000000000))
     }
@@ -53,26 +135,120 @@ impl Model {
         self.width = w;
         self.height = h;
         self.flowfield = self.flowfield.reset(w, h);
-        self.set_field();
+        self.set_field().reset_particles();
 
         self
     }
 
+    fn reset_particles(&mut self) -> &mut Self {
+        let (w, h) = (self.width, self.height);
+        let mut emitter = Emitter::new(Shape::rect(Vec2::ZERO, vec2(w, h)), 0.0);
+        emitter.lifetime(2.0, 6.0).set_seed(self.seed);
+
+        self.particles
+            .clear()
+            .set_seed(self.seed)
+            .drag(1.5)
+            .max_speed(250.0)
+            .bounds(Vec2::ZERO, vec2(w, h), Edges::Kill)
+            .respawn(move |_| emitter.particle())
+            .populate(PARTICLES);
+        self
+    }
+
     fn set_field(&mut self) -> &mut Self {
         self.flowfield.zero();
+        let center = self.index_at(0.5, 0.5);
+        if let Some(expr) = &mut self.expr {
+            expr.center(center[0] as f64, center[1] as f64);
+            self.flowfield
+                .set_recipe(expr.source())
+                .par_merge_at(expr, self.time);
+            return self.refresh_overlays();
+        }
+
         let pt1 = self.index_at(0.7, 0.7);
         let pt2 = self.index_at(0.3, 0.3);
+        let (ci, cj) = (center[0] as f64, center[1] as f64);
+
+        // the attractors circle the center over time, starting from their resting points
+        let orbit = |pt: [usize; 2]| {
+            let (oi, oj) = (pt[0] as f64 - ci, pt[1] as f64 - cj);
+            move |t: f64| {
+                let (sin, cos) = t.sin_cos();
+                [ci + oi * cos - oj * sin, cj + oi * sin + oj * cos]
+            }
+        };
+        let mut attractor1 = AttractorField::new(-1.0, pt1[0], pt1[1]);
+        attractor1.moving(orbit(pt1));
+        let mut attractor2 = AttractorField::new(-1.0, pt2[0], pt2[1]);
+        attractor2.moving(orbit(pt2));
 
-        self.flowfield
-            .merge(&AttractorField::new(-1.0, pt1[0], pt1[1]))
-            .merge(&AttractorField::new(-1.0, pt2[0], pt2[1]))
-            .merge(&PerlinField::new(0.5, 0.015, self.seed));
+        let mut perlin = PerlinField::new(0.5, 0.015, self.seed);
+        if self.animate {
+            perlin.animate(1.0);
+        }
+
+        if self.masked {
+            // perlin in a feathered disc at the center, the attractors everywhere else
+            let radius = (pt1[0] as f64 - ci).abs();
+            let disc = CircleMask::new([ci, cj], radius * 0.6, radius * 0.3);
+            self.flowfield
+                .set_recipe("2 attractors (-1.0) outside + perlin (0.5, 0.015) inside a disc")
+                .par_merge_at(&Masked::new(attractor1, Invert(disc)), self.time)
+                .par_merge_at(&Masked::new(attractor2, Invert(disc)), self.time)
+                .par_merge_at(&Masked::new(perlin, disc), self.time);
+        } else {
+            self.flowfield
+                .set_recipe("2 attractors (-1.0) + perlin (0.5, 0.015)")
+                .par_merge_at(&attractor1, self.time)
+                .par_merge_at(&attractor2, self.time)
+                .par_merge_at(&perlin, self.time);
+        }
+        self.refresh_overlays()
+    }
+
+    // rebuild the analysis overlay and streamlines for the current field, those that are on
+    fn refresh_overlays(&mut self) -> &mut Self {
+        if self.analysis.is_some() {
+            self.analysis = Some(self.analysis_overlay());
+        }
+        if self.streamlines.is_some() {
+            self.streamlines = Some(self.place_streamlines());
+        }
         self
     }
 
+    fn analysis_overlay(&self) -> (ScalarField, Vec<CriticalPoint>) {
+        (
+            self.flowfield.divergence(),
+            self.flowfield.critical_points(0.1),
+        )
+    }
+
+    fn place_streamlines(&self) -> Vec<Vec<Vec2>> {
+        let spacing = 3;
+        let [from, to] = self.flowfield.visible_bounds();
+        let seeds: Vec<Vec2> = (from[0]..to[0])
+            .step_by(spacing)
+            .flat_map(|i| (from[1]..to[1]).step_by(spacing).map(move |j| (i, j)))
+            .map(|(i, j)| self.flowfield.point_at(i, j))
+            .collect();
+
+        // grow from the center, the grid points only fill what the flow never reaches from there
+        let center = Vec2::new(self.width, self.height) / 2.0;
+        let seeds: Vec<Vec2> = std::iter::once(center).chain(seeds).collect();
+
+        let resolution = self.flowfield.resolution();
+        let mut tracer = Tracer::new(resolution / 8.0, 1000);
+        tracer.self_distance(resolution / 4.0);
+        EvenlySpaced::new(resolution / 2.0, 0.5)
+            .bounds(Vec2::ZERO, Vec2::new(self.width, self.height))
+            .place(&self.flowfield, &tracer, &seeds)
+    }
+
     fn index_at(&self, xs: f32, ys: f32) -> [usize; 2] {
-        self.flowfield
-            .index_at([xs * self.width as f32, ys * self.height as f32])
+        self.flowfield.index_at([xs * self.width, ys * self.height])
     }
 }
 
@@ -88,12 +264,43 @@ fn model(app: &App) -> Model {
 
     let seed = random_range(0, 1000000000);
     let mut model = Model::new(app, seed);
-    model.set_field();
+    model.load_field().set_field().reset_particles();
     model
 }
 
-fn update(_app: &App, _model: &mut Model, _update: Update) {
-    // Update the flowfield here
+fn update(_app: &App, model: &mut Model, update: Update) {
+    if model.animate {
+        model.time += update.since_last.as_secs_f64() * TIME_SPEED;
+        model.set_field();
+    }
+
+    if model.show_particles {
+        let field = &model.flowfield;
+        let elapsed = update.since_last.as_secs_f32();
+        let dt = model.particles.timestep();
+        let (boids, steering, exposure) = (model.boids, &mut model.steering, &mut model.exposure);
+
+        // faint, so that only paths taken again and again build up
+        let color = rgba(1.0, 0.85, 0.6, 0.02);
+        let before = |particles: &mut [Particle], t| {
+            // the step just taken, particles moved by velocity * dt
+            if let Some(canvas) = exposure {
+                for p in particles.iter() {
+                    canvas.segment(p.position - p.velocity * dt, p.position, color);
+                }
+            }
+            if boids {
+                steering.steer(particles, t, field);
+            }
+        };
+
+        if boids {
+            model.particles.update_with(elapsed, &(), before);
+        } else {
+            let push = |p: Vec2, _| field.sample(p.into()) * PARTICLE_FORCE;
+            model.particles.update_with(elapsed, &push, before);
+        }
+    }
 }
 
 fn view(app: &App, model: &Model, frame: Frame) {
@@ -103,7 +310,36 @@ fn view(app: &App, model: &Model, frame: Frame) {
         .x_y(model.width / -2.0 + 0.5, model.height / -2.0 + 0.5);
 
     draw.background().color(STEELBLUE);
-    model.flowfield.display(&gdraw, false, None);
+    if let Some((divergence, _)) = &model.analysis {
+        divergence.display(&gdraw, false, 0.5);
+    }
+    model
+        .flowfield
+        .display_mode(&gdraw, false, model.mode, None);
+    if model.show_particles {
+        for particle in model.particles.particles() {
+            let alpha = 1.0 - particle.life();
+            gdraw
+                .ellipse()
+                .xy(particle.position)
+                .radius(1.5)
+                .color(rgba(1.0, 1.0, 1.0, alpha));
+        }
+    }
+    if let Some(lines) = &model.streamlines {
+        for line in lines {
+            gdraw
+                .polyline()
+                .weight(1.5)
+                .points(line.iter().copied())
+                .color(BLACK);
+        }
+    }
+    if let Some((_, points)) = &model.analysis {
+        model
+            .flowfield
+            .display_critical_points(&gdraw, false, points);
+    }
     gdraw.ellipse().x_y(0.0, 0.0).color(RED).w_h(10.0, 10.0);
     draw.to_frame(app, &frame).unwrap();
 }
@@ -115,10 +351,97 @@ fn key_pressed(app: &App, model: &mut Model, key: Key) {
         Key::Space => {
             model.reset_seed().reset(w, h);
         }
+        Key::D => {
+            model.mode = model.mode.next();
+        }
+        Key::F => {
+            model.analysis = match model.analysis {
+                Some(_) => None,
+                None => Some(model.analysis_overlay()),
+            };
+        }
+        Key::T => {
+            model.streamlines = match model.streamlines {
+                Some(_) => None,
+                None => Some(model.place_streamlines()),
+            };
+        }
+        Key::I => {
+            // save a line integral convolution of the field
+            let fname = format!(
+                "generated/{}-{}-lic.png",
+                app.exe_name().unwrap(),
+                model.seed
+            );
+            let (w, h) = model.flowfield.size();
+            let lic = model.flowfield.lic(w as u32, h as u32, 20, model.seed);
+            if let Err(err) = lic.save(&fname) {
+                eprintln!("{}: {}", fname, err);
+            }
+        }
+        Key::S => {
+            // save the baked field for other sketches and tools
+            let fname = format!("generated/{}-{}", app.exe_name().unwrap(), model.seed);
+            let saved = model
+                .flowfield
+                .save(format!("{}.flow", fname))
+                .and_then(|_| model.flowfield.save_json(format!("{}.json", fname)))
+                .and_then(|_| model.flowfield.save_npy(format!("{}.npy", fname)));
+            if let Err(err) = saved {
+                eprintln!("{}: {}", fname, err);
+            }
+        }
+        Key::L => {
+            model.load_field().set_field();
+        }
+        Key::M => {
+            model.masked = !model.masked;
+            model.set_field();
+        }
+        Key::A => {
+            model.animate = !model.animate;
+            set_loop_mode(app, model);
+        }
+        Key::P => {
+            model.show_particles = !model.show_particles;
+            set_loop_mode(app, model);
+        }
+        Key::B => {
+            model.boids = !model.boids;
+        }
+        Key::E => {
+            // start a long exposure of the particles, or stop and save it
+            match model.exposure.take() {
+                Some(canvas) => {
+                    let fname = format!(
+                        "generated/{}-{}-exposure.png",
+                        app.exe_name().unwrap(),
+                        model.seed
+                    );
+                    if let Err(err) = canvas.save(&fname, ToneMap::Filmic, 1.0) {
+                        eprintln!("{}: {}", fname, err);
+                    }
+                }
+                None => {
+                    let mut canvas = Canvas::new(model.width as u32, model.height as u32);
+                    canvas.blend(Blend::Add);
+                    model.exposure = Some(canvas);
+                }
+            }
+        }
         _ => (),
     }
 }
 
+// redraw every frame only while something moves
+fn set_loop_mode(app: &App, model: &Model) {
+    if model.animate || model.show_particles {
+        app.set_loop_mode(LoopMode::refresh_sync());
+    } else {
+        app.set_loop_mode(LoopMode::wait());
+    }
+}
+
 fn window_resized(_app: &App, model: &mut Model, dim: Vec2) {
     let (w, h) = (dim.x - 2.0 * MARGIN, dim.y - 2.0 * MARGIN);
     model.reset(w, h);
@@ -126,5 +449,5 @@ fn window_resized(_app: &App, model: &mut Model, dim: Vec2) {
 
 fn get_window_size(app: &App) -> (f32, f32) {
     let rect = app.window_rect().pad(MARGIN);
-    return (rect.w(), rect.h());
+    (rect.w(), rect.h())
 }
@@ -0,0 +1,43 @@
+// serial vs parallel baking of a perlin field into grids of increasing size
+//
+//     cargo bench -p lib --bench merge
+
+use lib::forces::field::PerlinField;
+use lib::forces::map::FlowField;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 10;
+
+fn time(mut f: impl FnMut()) -> Duration {
+    f();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let perlin = PerlinField::new(1.0, 0.015, 0);
+
+    for resolution in [5.0, 4.0, 3.0, 2.0] {
+        let mut field = FlowField::new(1920.0, 1080.0, resolution, 1.0);
+        let serial = time(|| {
+            black_box(field.zero().merge(&perlin));
+        });
+        let parallel = time(|| {
+            black_box(field.zero().par_merge(&perlin));
+        });
+
+        println!(
+            "resolution {:>3}: {:>4} x {:>4} cells | merge {:>10.2?} | par_merge {:>10.2?} | {:.1}x",
+            resolution,
+            field.cols(),
+            field.rows(),
+            serial,
+            parallel,
+            serial.as_secs_f64() / parallel.as_secs_f64()
+        );
+    }
+}
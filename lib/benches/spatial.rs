@@ -0,0 +1,113 @@
+// radius and k-nearest queries: brute force vs spatial hash vs k-d tree,
+// over random points in a 1920x1080 area
+//
+//     cargo bench -p lib --bench spatial
+
+use lib::spatial::hash::SpatialHash;
+use lib::spatial::kdtree::KdTree;
+use lib::spatial::Neighbors;
+use lib::utils::rng;
+use nannou::glam::{vec2, Vec2};
+use nannou::rand::Rng;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const QUERIES: usize = 1000;
+const RADIUS: f32 = 20.0;
+const K: usize = 8;
+
+fn time(mut f: impl FnMut()) -> Duration {
+    f();
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn brute_within(points: &[Vec2], center: Vec2, radius: f32) -> Vec<usize> {
+    (0..points.len())
+        .filter(|n| points[*n].distance_squared(center) <= radius * radius)
+        .collect()
+}
+
+fn brute_nearest(points: &[Vec2], point: Vec2, k: usize) -> Vec<usize> {
+    let distance = |n: &usize| points[*n].distance_squared(point);
+    let mut indices: Vec<usize> = (0..points.len()).collect();
+    if k < indices.len() {
+        indices.select_nth_unstable_by(k, |a, b| distance(a).total_cmp(&distance(b)));
+        indices.truncate(k);
+    }
+    indices.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+    indices
+}
+
+fn main() {
+    let mut rng = rng(0);
+    let mut random = |n: usize| -> Vec<Vec2> {
+        (0..n)
+            .map(|_| vec2(rng.gen_range(0.0..1920.0), rng.gen_range(0.0..1080.0)))
+            .collect()
+    };
+    let queries = random(QUERIES);
+
+    for n in [1_000, 10_000, 100_000] {
+        let points = random(n);
+        let build_hash = time(|| {
+            black_box(SpatialHash::from_points(RADIUS, &points));
+        });
+        let build_tree = time(|| {
+            black_box(KdTree::new(&points));
+        });
+        let hash = SpatialHash::from_points(RADIUS, &points);
+        let tree = KdTree::new(&points);
+
+        // same answers from all three before timing them
+        for q in &queries {
+            let mut expected = brute_within(&points, *q, RADIUS);
+            let (mut a, mut b) = (hash.within(*q, RADIUS), tree.within(*q, RADIUS));
+            expected.sort_unstable();
+            a.sort_unstable();
+            b.sort_unstable();
+            assert!(a == expected && b == expected, "radius query mismatch");
+
+            let expected = brute_nearest(&points, *q, K);
+            let d = |i: &Vec<usize>| -> Vec<f32> {
+                i.iter().map(|n| points[*n].distance_squared(*q)).collect()
+            };
+            assert!(
+                d(&hash.nearest(*q, K)) == d(&expected) && d(&tree.nearest(*q, K)) == d(&expected),
+                "nearest query mismatch"
+            );
+        }
+
+        let run = |f: &dyn Fn(Vec2) -> Vec<usize>| {
+            time(|| queries.iter().for_each(|q| drop(black_box(f(*q)))))
+        };
+        let within = [
+            run(&|q| brute_within(&points, q, RADIUS)),
+            run(&|q| hash.within(q, RADIUS)),
+            run(&|q| tree.within(q, RADIUS)),
+        ];
+        let nearest = [
+            run(&|q| brute_nearest(&points, q, K)),
+            run(&|q| hash.nearest(q, K)),
+            run(&|q| tree.nearest(q, K)),
+        ];
+
+        println!("{} points, {} queries", n, QUERIES);
+        println!(
+            "  build     |              | hash {:>10.2?} | kd-tree {:>10.2?}",
+            build_hash, build_tree
+        );
+        for (name, [brute, hash, tree]) in [("within r", within), ("nearest k", nearest)] {
+            println!(
+                "  {:<9} | brute {:>10.2?} | hash {:>10.2?} ({:>6.1}x) | kd-tree {:>10.2?} ({:>6.1}x)",
+                name,
+                brute,
+                hash,
+                brute.as_secs_f64() / hash.as_secs_f64(),
+                tree,
+                brute.as_secs_f64() / tree.as_secs_f64()
+            );
+        }
+    }
+}
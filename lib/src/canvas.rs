@@ -0,0 +1,339 @@
+use nannou::color::Rgba;
+use nannou::glam::Vec2;
+use nannou::image::{ImageResult, RgbaImage};
+use std::path::Path;
+
+// How a new sample combines with what is already on the canvas
+//
+// Add   : light adds up, dense areas grow brighter than 1 until tone mapped
+// Alpha : painted over with the sample's alpha, never brighter than the colors used
+// Max   : keeps the brightest value per channel, nothing builds up
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Blend {
+    Add,
+    Alpha,
+    Max,
+}
+
+// How accumulated values are brought to 8 bits per channel, after scaling by the exposure
+//
+// Linear : clipped at 1
+// Log    : log(1 + v) relative to the brightest value, keeps faint paths visible
+// Filmic : ACES curve, rolls bright areas off smoothly instead of clipping them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMap {
+    Linear,
+    Log,
+    Filmic,
+}
+
+// A floating point RGBA image that samples are accumulated into over many frames,
+// for long exposures of particles and lines that a per-frame `Draw` cannot keep.
+//
+// Points are splatted bilinearly over the four pixels around them, so slow particles
+// leave smooth trails. Positions are in pixels (x right, y down) unless `view` maps a
+// world rectangle onto the canvas.
+//
+//     let mut canvas = Canvas::new(800, 800);
+//     canvas.blend(Blend::Add);
+//     // every frame
+//     canvas.fade(0.99).points(positions, Rgba::new(1.0, 0.8, 0.6, 0.05));
+//     // at the end
+//     canvas.save("exposure.png", ToneMap::Filmic, 2.0)?;
+pub struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<[f32; 4]>,
+    blend: Blend,
+    // world to pixel: pixel = (world - origin) * scale
+    origin: Vec2,
+    scale: Vec2,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Self {
+        let (width, height) = (width.max(1) as usize, height.max(1) as usize);
+        Canvas {
+            width,
+            height,
+            pixels: vec![[0.0; 4]; width * height],
+            blend: Blend::Add,
+            origin: Vec2::ZERO,
+            scale: Vec2::ONE,
+        }
+    }
+
+    pub fn blend(&mut self, blend: Blend) -> &mut Self {
+        self.blend = blend;
+        self
+    }
+
+    // positions are in world units from now on, with `min` at the top left pixel corner
+    // and `max` at the bottom right one
+    pub fn view(&mut self, min: Vec2, max: Vec2) -> &mut Self {
+        let size = (max - min).abs().max(Vec2::splat(f32::EPSILON));
+        self.origin = min.min(max);
+        self.scale = Vec2::new(self.width as f32, self.height as f32) / size;
+        self
+    }
+
+    pub fn clear(&mut self) -> &mut Self {
+        self.pixels.fill([0.0; 4]);
+        self
+    }
+
+    // multiplies everything by `factor`, e.g. 0.98 every frame for trails that fade out
+    pub fn fade(&mut self, factor: f32) -> &mut Self {
+        let factor = factor.clamp(0.0, 1.0);
+        for pixel in self.pixels.iter_mut() {
+            pixel.iter_mut().for_each(|c| *c *= factor);
+        }
+        self
+    }
+
+    pub fn point(&mut self, point: Vec2, color: Rgba) -> &mut Self {
+        let p = (point - self.origin) * self.scale - Vec2::splat(0.5);
+        self.splat(p, color, 1.0);
+        self
+    }
+
+    pub fn points(&mut self, points: impl IntoIterator<Item = Vec2>, color: Rgba) -> &mut Self {
+        points.into_iter().for_each(|p| {
+            self.point(p, color);
+        });
+        self
+    }
+
+    // a line with about one sample per pixel along it, each sample weighted so that
+    // a segment adds the same amount per pixel of length whatever its direction.
+    // Only the part over the canvas is sampled, and skipped if its length is not finite.
+    pub fn segment(&mut self, from: Vec2, to: Vec2, color: Rgba) -> &mut Self {
+        let a = (from - self.origin) * self.scale - Vec2::splat(0.5);
+        let b = (to - self.origin) * self.scale - Vec2::splat(0.5);
+        // splats reach one pixel past their position, so keep what is within a pixel
+        let size = Vec2::new(self.width as f32, self.height as f32);
+        let (a, b) = match clip(a, b, Vec2::splat(-1.0), size) {
+            Some(ab) => ab,
+            None => return self,
+        };
+        let length = a.distance(b);
+        if !length.is_finite() {
+            return self;
+        }
+        let samples = length.ceil().max(1.0) as usize;
+        let weight = (length / samples as f32).min(1.0);
+        for n in 0..samples {
+            let t = (n as f32 + 0.5) / samples as f32;
+            self.splat(a.lerp(b, t), color, weight);
+        }
+        self
+    }
+
+    pub fn polyline(&mut self, points: &[Vec2], color: Rgba) -> &mut Self {
+        for pair in points.windows(2) {
+            self.segment(pair[0], pair[1], color);
+        }
+        self
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height as u32
+    }
+
+    // accumulated (r, g, b, a) at a pixel, None outside the canvas
+    pub fn get(&self, x: u32, y: u32) -> Option<[f32; 4]> {
+        let (x, y) = (x as usize, y as usize);
+        (x < self.width && y < self.height).then(|| self.pixels[y * self.width + x])
+    }
+
+    // the largest accumulated channel value, what `ToneMap::Log` maps to white
+    pub fn max_value(&self) -> f32 {
+        self.pixels
+            .iter()
+            .flat_map(|p| p.iter())
+            .fold(0.0, |a, c| a.max(*c))
+    }
+
+    pub fn to_image(&self, tone: ToneMap, exposure: f32) -> RgbaImage {
+        let exposure = exposure.max(0.0);
+        let log_white = (1.0 + self.max_value() * exposure).ln().max(f32::EPSILON);
+        let map = |v: f32| {
+            let v = v * exposure;
+            let mapped = match tone {
+                ToneMap::Linear => v,
+                ToneMap::Log => (1.0 + v).ln() / log_white,
+                ToneMap::Filmic => aces(v),
+            };
+            (mapped.clamp(0.0, 1.0) * 255.0).round() as u8
+        };
+
+        RgbaImage::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let [r, g, b, a] = self.pixels[y as usize * self.width + x as usize];
+            // alpha is coverage, it only clips
+            let alpha = (a.clamp(0.0, 1.0) * 255.0).round() as u8;
+            [map(r), map(g), map(b), alpha].into()
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>, tone: ToneMap, exposure: f32) -> ImageResult<()> {
+        self.to_image(tone, exposure).save(path)
+    }
+
+    // blends a sample at pixel coordinates p into the four pixels around it
+    fn splat(&mut self, p: Vec2, color: Rgba, weight: f32) {
+        let (x0, y0) = (p.x.floor(), p.y.floor());
+        let (fx, fy) = (p.x - x0, p.y - y0);
+        for (dx, dy, w) in [
+            (0, 0, (1.0 - fx) * (1.0 - fy)),
+            (1, 0, fx * (1.0 - fy)),
+            (0, 1, (1.0 - fx) * fy),
+            (1, 1, fx * fy),
+        ] {
+            let (x, y) = (x0 as i64 + dx, y0 as i64 + dy);
+            if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 || w <= 0.0 {
+                continue;
+            }
+            let pixel = &mut self.pixels[y as usize * self.width + x as usize];
+            blend(pixel, color, w * weight, self.blend);
+        }
+    }
+}
+
+fn blend(pixel: &mut [f32; 4], color: Rgba, weight: f32, mode: Blend) {
+    let a = color.alpha * weight;
+    let rgb = [color.red, color.green, color.blue];
+    match mode {
+        Blend::Add => {
+            (0..3).for_each(|n| pixel[n] += rgb[n] * a);
+            pixel[3] += a;
+        }
+        Blend::Alpha => {
+            let a = a.min(1.0);
+            (0..3).for_each(|n| pixel[n] = pixel[n] * (1.0 - a) + rgb[n] * a);
+            pixel[3] = pixel[3] * (1.0 - a) + a;
+        }
+        Blend::Max => {
+            (0..3).for_each(|n| pixel[n] = pixel[n].max(rgb[n] * a));
+            pixel[3] = pixel[3].max(a);
+        }
+    }
+}
+
+// the part of the segment ab inside the rectangle between min and max, cut at one edge
+// after the other with the cut points placed exactly on the edge, so far away endpoints
+// do not lose the segment to rounding
+fn clip(mut a: Vec2, mut b: Vec2, min: Vec2, max: Vec2) -> Option<(Vec2, Vec2)> {
+    for axis in 0..2 {
+        let other = 1 - axis;
+        for (bound, below) in [(min[axis], true), (max[axis], false)] {
+            let outside = |p: Vec2| {
+                if below {
+                    p[axis] < bound
+                } else {
+                    p[axis] > bound
+                }
+            };
+            let cut = |p: Vec2, q: Vec2| {
+                let mut c = Vec2::ZERO;
+                c[axis] = bound;
+                c[other] =
+                    p[other] + (bound - p[axis]) * (q[other] - p[other]) / (q[axis] - p[axis]);
+                c
+            };
+            match (outside(a), outside(b)) {
+                (true, true) => return None,
+                (true, false) => a = cut(a, b),
+                (false, true) => b = cut(a, b),
+                (false, false) => (),
+            }
+        }
+    }
+    Some((a, b))
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(v: f32) -> f32 {
+    let v = v.max(0.0);
+    (v * (2.51 * v + 0.03)) / (v * (2.43 * v + 0.59) + 0.14)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn white() -> Rgba {
+        Rgba::new(1.0, 1.0, 1.0, 0.5)
+    }
+
+    fn total(canvas: &Canvas) -> f32 {
+        (0..canvas.height())
+            .flat_map(|y| (0..canvas.width()).map(move |x| (x, y)))
+            .map(|(x, y)| canvas.get(x, y).unwrap()[3])
+            .sum()
+    }
+
+    #[test]
+    fn points_splat_bilinearly_and_add_up() {
+        let mut canvas = Canvas::new(4, 4);
+        canvas.point(Vec2::new(1.5, 1.5), white());
+        assert_eq!(canvas.get(1, 1), Some([0.5; 4]));
+
+        // halfway between two pixel centers, split evenly
+        canvas
+            .clear()
+            .point(Vec2::new(2.0, 1.5), white())
+            .point(Vec2::new(2.0, 1.5), white());
+        assert_eq!(canvas.get(1, 1), Some([0.5; 4]));
+        assert_eq!(canvas.get(2, 1), Some([0.5; 4]));
+        assert_eq!(canvas.get(4, 1), None);
+    }
+
+    #[test]
+    fn max_blend_and_fade() {
+        let mut canvas = Canvas::new(2, 2);
+        canvas.blend(Blend::Max);
+        for _ in 0..10 {
+            canvas.point(Vec2::splat(0.5), white());
+        }
+        assert_eq!(canvas.get(0, 0), Some([0.5; 4]));
+        canvas.fade(0.5);
+        assert_eq!(canvas.get(0, 0), Some([0.25; 4]));
+    }
+
+    #[test]
+    fn segments_add_the_same_per_pixel_of_length() {
+        let mut straight = Canvas::new(64, 64);
+        straight.segment(Vec2::new(10.0, 32.0), Vec2::new(50.0, 32.0), white());
+        let mut diagonal = Canvas::new(64, 64);
+        diagonal.segment(Vec2::new(10.0, 10.0), Vec2::new(50.0, 50.0), white());
+
+        assert!((total(&straight) - 40.0 * 0.5).abs() < 1e-3);
+        assert!((total(&diagonal) - 40.0 * 2f32.sqrt() * 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn segments_are_clipped_to_the_canvas() {
+        let mut canvas = Canvas::new(100, 50);
+        canvas.segment(Vec2::new(-1e30, 25.5), Vec2::new(1e30, 25.5), white());
+        assert!((0..100).all(|x| canvas.get(x, 25).unwrap()[3] > 0.4));
+
+        let mut canvas = Canvas::new(10, 10);
+        canvas
+            .segment(Vec2::new(f32::NAN, 5.0), Vec2::new(5.0, 5.0), white())
+            .segment(Vec2::new(f32::INFINITY, 5.0), Vec2::new(5.0, 5.0), white())
+            .segment(Vec2::new(-50.0, -5.0), Vec2::new(50.0, -5.0), white());
+        assert_eq!(total(&canvas), 0.0);
+    }
+
+    #[test]
+    fn view_maps_world_units_to_pixels() {
+        let mut canvas = Canvas::new(10, 10);
+        canvas.view(Vec2::splat(-1.0), Vec2::splat(1.0));
+        canvas.point(Vec2::new(-0.9, 0.9), white());
+        assert!((canvas.get(0, 9).unwrap()[3] - 0.5).abs() < 1e-5);
+    }
+}
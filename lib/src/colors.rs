@@ -37,16 +37,16 @@ impl Color {
 // we can use generics for Color class to specify whitepoint,
 // but it's not necessary for this example.
 // Primarily, its too early to use generics in our journey, IMO.
-impl Into<Lcha<D65, f32>> for Color {
-    fn into(self) -> Lcha<D65, f32> {
-        Lcha::new(self.l * 100.0, self.c * 128.0, self.h * 360.0, self.a)
+impl From<Color> for Lcha<D65, f32> {
+    fn from(color: Color) -> Self {
+        Lcha::new(color.l * 100.0, color.c * 128.0, color.h * 360.0, color.a)
     }
 }
 
 // use the Color to lcha conversion to convert to rgba
-impl Into<Rgba> for Color {
-    fn into(self) -> Rgba {
-        let lcha: Lcha<D65, f32> = self.into();
+impl From<Color> for Rgba {
+    fn from(color: Color) -> Self {
+        let lcha: Lcha<D65, f32> = color.into();
         lcha.into()
     }
 }
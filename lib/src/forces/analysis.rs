@@ -0,0 +1,354 @@
+use crate::forces::map::FlowField;
+use crate::forces::scalar::ScalarField;
+use nannou::color::Rgba;
+use nannou::glam::Vec2;
+use nannou::prelude::TAU;
+use nannou::Draw;
+
+// Tools to understand a baked field: where it spreads out or converges (divergence),
+// where it swirls (curl), how strong it is, and where it comes to rest (critical points).
+//
+// Derivatives use central differences in world units (one-sided at the borders),
+// with y pointing down the grid like the field indices.
+
+// all zero for a field without cells
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MagnitudeStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub std_dev: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CriticalKind {
+    // flow leaves in every direction (repelling node or focus)
+    Source,
+    // flow arrives from every direction (attracting node or focus)
+    Sink,
+    // flow arrives along one axis and leaves along the other
+    Saddle,
+    // flow circles around without arriving or leaving
+    Center,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CriticalPoint {
+    // world position, same space as `FlowField::point_at`
+    pub position: Vec2,
+    // cell whose corners enclose the point
+    pub cell: [usize; 2],
+    pub kind: CriticalKind,
+    // whether the flow spirals in or out instead of moving straight
+    pub spiral: bool,
+    // [[dvx/dx, dvx/dy], [dvy/dx, dvy/dy]]
+    pub jacobian: [[f32; 2]; 2],
+}
+
+impl FlowField {
+    // partial derivatives of the field at a cell, per world unit, zero outside the grid
+    pub fn jacobian(&self, i: usize, j: usize) -> [[f32; 2]; 2] {
+        if i >= self.cols() || j >= self.rows() {
+            return [[0.0; 2]; 2];
+        }
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.cols() - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.rows() - 1));
+        let dx = ((i1 - i0) as f32 * self.resolution()).max(f32::EPSILON);
+        let dy = ((j1 - j0) as f32 * self.resolution()).max(f32::EPSILON);
+
+        let ddx = (self.cell(i1, j) - self.cell(i0, j)) / dx;
+        let ddy = (self.cell(i, j1) - self.cell(i, j0)) / dy;
+        [[ddx.x, ddy.x], [ddx.y, ddy.y]]
+    }
+
+    pub fn divergence(&self) -> ScalarField {
+        self.derived(|[[a, _], [_, d]]| a + d)
+    }
+
+    // z component of the curl, positive where the flow turns from +x towards +y
+    pub fn curl(&self) -> ScalarField {
+        self.derived(|[[_, b], [c, _]]| c - b)
+    }
+
+    pub fn magnitude(&self) -> ScalarField {
+        let mut out = ScalarField::like(self);
+        for i in 0..self.cols() {
+            for j in 0..self.rows() {
+                out.set(i, j, self.cell(i, j).length());
+            }
+        }
+        out
+    }
+
+    pub fn magnitude_stats(&self) -> MagnitudeStats {
+        let magnitudes: Vec<f32> = self.cells().iter().map(|v| v.length()).collect();
+        if magnitudes.is_empty() {
+            return MagnitudeStats::default();
+        }
+        let n = magnitudes.len() as f32;
+        let mean = magnitudes.iter().sum::<f32>() / n;
+        let variance = magnitudes
+            .iter()
+            .map(|m| (m - mean) * (m - mean))
+            .sum::<f32>()
+            / n;
+
+        MagnitudeStats {
+            min: magnitudes.iter().copied().fold(f32::MAX, f32::min),
+            max: magnitudes.iter().copied().fold(0.0, f32::max),
+            mean,
+            std_dev: variance.sqrt(),
+        }
+    }
+
+    // count of cells per direction, bin 0 starts at angle 0 (+x) and bins go counter-clockwise
+    // zero vectors have no direction and are left out
+    pub fn angle_histogram(&self, bins: usize) -> Vec<usize> {
+        let bins = bins.max(1);
+        let mut histogram = vec![0; bins];
+        for v in self.cells() {
+            if v.length_squared() <= f32::EPSILON {
+                continue;
+            }
+            let angle = v.y.atan2(v.x).rem_euclid(TAU);
+            histogram[((angle / TAU * bins as f32) as usize).min(bins - 1)] += 1;
+        }
+        histogram
+    }
+
+    // points where the field vanishes, found per cell where both components change sign
+    // across its corners, and classified from the jacobian there
+    //
+    // tolerance : how close to zero the trace (relative to the eigenvalue size)
+    //             must be for a rotation to count as a center instead of a spiral
+    pub fn critical_points(&self, tolerance: f32) -> Vec<CriticalPoint> {
+        let mut points: Vec<CriticalPoint> = Vec::new();
+
+        for i in 0..self.cols().saturating_sub(1) {
+            for j in 0..self.rows().saturating_sub(1) {
+                let corners = [
+                    self.cell(i, j),
+                    self.cell(i + 1, j),
+                    self.cell(i, j + 1),
+                    self.cell(i + 1, j + 1),
+                ];
+                if !changes_sign(corners.map(|v| v.x)) || !changes_sign(corners.map(|v| v.y)) {
+                    continue;
+                }
+
+                let (u, v) = match bilinear_zero(corners) {
+                    Some(zero) => zero,
+                    None => continue,
+                };
+                let position = self.point_at(i, j) + Vec2::new(u, v) * self.resolution();
+                if points
+                    .iter()
+                    .any(|p| p.position.distance(position) < self.resolution() / 2.0)
+                {
+                    continue;
+                }
+
+                let nearest = [i + (u >= 0.5) as usize, j + (v >= 0.5) as usize];
+                let jacobian = self.jacobian(nearest[0], nearest[1]);
+                let (kind, spiral) = classify(jacobian, tolerance);
+
+                points.push(CriticalPoint {
+                    position,
+                    cell: [i, j],
+                    kind,
+                    spiral,
+                    jacobian,
+                });
+            }
+        }
+
+        points
+    }
+
+    // markers for critical points: sources red, sinks blue, saddles as a cross, centers as a ring
+    pub fn display_critical_points(&self, draw: &Draw, complete: bool, points: &[CriticalPoint]) {
+        let size = self.resolution() * 0.6;
+        for point in points {
+            let p = if complete {
+                self.map_to_visible(point.position)
+            } else {
+                point.position
+            };
+
+            match point.kind {
+                CriticalKind::Source => {
+                    draw.ellipse()
+                        .xy(p)
+                        .radius(size / 2.0)
+                        .color(Rgba::new(0.85, 0.2, 0.15, 0.9));
+                }
+                CriticalKind::Sink => {
+                    draw.ellipse()
+                        .xy(p)
+                        .radius(size / 2.0)
+                        .color(Rgba::new(0.15, 0.3, 0.85, 0.9));
+                }
+                CriticalKind::Saddle => {
+                    let color = Rgba::new(0.1, 0.1, 0.1, 0.9);
+                    let (a, b) = (Vec2::new(size, size) / 2.0, Vec2::new(size, -size) / 2.0);
+                    draw.line().start(p - a).end(p + a).weight(2.0).color(color);
+                    draw.line().start(p - b).end(p + b).weight(2.0).color(color);
+                }
+                CriticalKind::Center => {
+                    draw.ellipse()
+                        .xy(p)
+                        .radius(size / 2.0)
+                        .no_fill()
+                        .stroke_weight(2.0)
+                        .stroke(Rgba::new(0.1, 0.6, 0.3, 0.9));
+                }
+            }
+        }
+    }
+
+    fn derived(&self, f: impl Fn([[f32; 2]; 2]) -> f32) -> ScalarField {
+        let mut out = ScalarField::like(self);
+        for i in 0..self.cols() {
+            for j in 0..self.rows() {
+                out.set(i, j, f(self.jacobian(i, j)));
+            }
+        }
+        out
+    }
+}
+
+fn changes_sign(values: [f32; 4]) -> bool {
+    let lo = values.iter().copied().fold(f32::MAX, f32::min);
+    let hi = values.iter().copied().fold(f32::MIN, f32::max);
+    lo <= 0.0 && hi >= 0.0 && lo < hi
+}
+
+// zero of the bilinear interpolation over a cell, in cell-local coordinates [0, 1]²
+// corners are (0, 0), (1, 0), (0, 1), (1, 1); a few newton steps from the center
+fn bilinear_zero(c: [Vec2; 4]) -> Option<(f32, f32)> {
+    let (mut u, mut v) = (0.5, 0.5);
+    for _ in 0..12 {
+        let f = c[0] * (1.0 - u) * (1.0 - v)
+            + c[1] * u * (1.0 - v)
+            + c[2] * (1.0 - u) * v
+            + c[3] * u * v;
+        let du = (c[1] - c[0]) * (1.0 - v) + (c[3] - c[2]) * v;
+        let dv = (c[2] - c[0]) * (1.0 - u) + (c[3] - c[1]) * u;
+        let det = du.x * dv.y - dv.x * du.y;
+        if det.abs() <= f32::EPSILON {
+            return None;
+        }
+
+        u -= (f.x * dv.y - dv.x * f.y) / det;
+        v -= (du.x * f.y - f.x * du.y) / det;
+    }
+
+    let inside = |t: f32| (-1e-3..=1.0 + 1e-3).contains(&t);
+    (inside(u) && inside(v)).then_some((u.clamp(0.0, 1.0), v.clamp(0.0, 1.0)))
+}
+
+fn classify([[a, b], [c, d]]: [[f32; 2]; 2], tolerance: f32) -> (CriticalKind, bool) {
+    let trace = a + d;
+    let det = a * d - b * c;
+    if det < 0.0 {
+        return (CriticalKind::Saddle, false);
+    }
+
+    let discriminant = trace * trace - 4.0 * det;
+    let spiral = discriminant < 0.0;
+    if spiral && trace.abs() <= tolerance * det.sqrt() {
+        (CriticalKind::Center, true)
+    } else if trace > 0.0 {
+        (CriticalKind::Source, spiral)
+    } else {
+        (CriticalKind::Sink, spiral)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 100 x 80 field with 10 unit cells, filled with v(p) at every cell position
+    fn linear(v: impl Fn(Vec2) -> Vec2) -> FlowField {
+        let mut field = FlowField::new(100.0, 80.0, 10.0, 0.0);
+        for j in 0..field.rows() {
+            for i in 0..field.cols() {
+                field.set_cell(i, j, v(field.point_at(i, j)));
+            }
+        }
+        field
+    }
+
+    fn assert_all(values: &ScalarField, expected: f32) {
+        for v in values.values() {
+            assert!((v - expected).abs() < 1e-4, "{} != {}", v, expected);
+        }
+    }
+
+    #[test]
+    fn divergence_and_curl_of_linear_fields() {
+        // v = (a x + b y, c x + d y): divergence a + d, curl c - b
+        let field = linear(|p| Vec2::new(0.5 * p.x + 2.0 * p.y, -1.5 * p.x + 0.25 * p.y));
+        assert_all(&field.divergence(), 0.75);
+        assert_all(&field.curl(), -3.5);
+
+        let rotation = linear(|p| Vec2::new(-p.y, p.x));
+        assert_all(&rotation.divergence(), 0.0);
+        assert_all(&rotation.curl(), 2.0);
+    }
+
+    fn single_point(v: impl Fn(Vec2) -> Vec2) -> CriticalPoint {
+        let center = Vec2::new(53.0, 37.0);
+        let points = linear(|p| v(p - center)).critical_points(0.1);
+        assert_eq!(points.len(), 1, "{:?}", points);
+        assert!(points[0].position.distance(center) < 1e-3);
+        points[0]
+    }
+
+    #[test]
+    fn critical_points_of_a_saddle_a_source_and_a_center() {
+        let saddle = single_point(|d| Vec2::new(d.x, -d.y));
+        assert_eq!((saddle.kind, saddle.spiral), (CriticalKind::Saddle, false));
+
+        let source = single_point(|d| d);
+        assert_eq!((source.kind, source.spiral), (CriticalKind::Source, false));
+
+        let center = single_point(|d| Vec2::new(-d.y, d.x));
+        assert_eq!((center.kind, center.spiral), (CriticalKind::Center, true));
+    }
+
+    #[test]
+    fn classify_jacobians() {
+        assert_eq!(
+            classify([[1.0, 0.0], [0.0, -1.0]], 0.1),
+            (CriticalKind::Saddle, false)
+        );
+        assert_eq!(
+            classify([[1.0, 0.0], [0.0, 2.0]], 0.1),
+            (CriticalKind::Source, false)
+        );
+        assert_eq!(
+            classify([[-1.0, 0.0], [0.0, -1.0]], 0.1),
+            (CriticalKind::Sink, false)
+        );
+        assert_eq!(
+            classify([[0.0, -1.0], [1.0, 0.0]], 0.1),
+            (CriticalKind::Center, true)
+        );
+        assert_eq!(
+            classify([[0.5, -1.0], [1.0, 0.5]], 0.1),
+            (CriticalKind::Source, true)
+        );
+    }
+
+    #[test]
+    fn empty_field() {
+        let field = FlowField::new(0.0, 0.0, 10.0, 0.0);
+        assert!(field.cells().is_empty());
+        assert_eq!(field.jacobian(0, 0), [[0.0; 2]; 2]);
+        assert_eq!(field.magnitude_stats(), MagnitudeStats::default());
+        assert!(field.divergence().values().is_empty());
+        assert!(field.critical_points(0.1).is_empty());
+    }
+}
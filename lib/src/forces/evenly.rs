@@ -0,0 +1,199 @@
+use crate::forces::map::FlowField;
+use crate::forces::scalar::ScalarField;
+use crate::forces::streamline::{Direction, Tracer};
+use crate::spatial::hash::SpatialHash;
+use nannou::glam::Vec2;
+use std::collections::VecDeque;
+
+// Evenly spaced streamlines (Jobard & Lefer, 1997).
+//
+// Lines are traced one at a time. Every new line is seeded `separation` away from a line
+// that is already placed, on either side of each of its points, and only if no other line
+// passes closer than `separation` to that seed. A line being traced stops as soon as it
+// comes within `test * separation` of another line, so lines merge into each other
+// instead of piling up. A `SpatialHash` of `separation`-sized cells keeps the distance checks cheap.
+//
+// The separation is either constant or read from a scalar field (in world units),
+// e.g. a remapped noise field for denser and sparser regions.
+//
+//     let mut tracer = Tracer::new(1.0, 1000);
+//     let lines = EvenlySpaced::new(8.0, 0.5).place(&field, &tracer, &[center]);
+
+pub struct EvenlySpaced {
+    separation: f32,
+    test: f32,
+    separation_field: Option<ScalarField>,
+    min_points: usize,
+    bounds: Option<[Vec2; 2]>,
+}
+
+impl EvenlySpaced {
+    pub const DEFAULT_MIN_POINTS: usize = 3;
+
+    // separation : distance between neighbouring lines, in world units
+    // test       : fraction of the separation at which a line being traced stops, usually 0.5
+    pub fn new(separation: f32, test: f32) -> Self {
+        EvenlySpaced {
+            separation: separation.max(f32::EPSILON),
+            test: test.clamp(0.0, 1.0),
+            separation_field: None,
+            min_points: Self::DEFAULT_MIN_POINTS,
+            bounds: None,
+        }
+    }
+
+    // read the separation at every point from a scalar field instead of the constant,
+    // values below a tenth of the constant separation are raised to it
+    pub fn separation_field(&mut self, field: ScalarField) -> &mut Self {
+        self.separation_field = Some(field);
+        self
+    }
+
+    // lines with fewer points are dropped and do not seed new lines
+    pub fn min_points(&mut self, points: usize) -> &mut Self {
+        self.min_points = points.max(2);
+        self
+    }
+
+    // keep lines within a world rectangle, e.g. the visible area of an extended field
+    pub fn bounds(&mut self, min: Vec2, max: Vec2) -> &mut Self {
+        self.bounds = Some([min.min(max), min.max(max)]);
+        self
+    }
+
+    pub fn separation_at(&self, point: Vec2) -> f32 {
+        match &self.separation_field {
+            Some(field) => field.sample(point.into()).max(self.separation / 10.0),
+            None => self.separation,
+        }
+    }
+
+    // place lines over the field, starting from the first usable seed and growing from
+    // the placed lines; the other seeds are only used once no placed line can seed more,
+    // which fills regions the flow never reaches from the first one
+    pub fn place(&self, field: &FlowField, tracer: &Tracer, seeds: &[Vec2]) -> Vec<Vec<Vec2>> {
+        // nothing to follow on a field without cells, e.g. for a zero-size window
+        if field.cells().is_empty() {
+            return Vec::new();
+        }
+        let mut tracer = *tracer;
+        tracer.direction(Direction::Both);
+
+        let [min, max] = self.bounds.unwrap_or([
+            field.point_at(0, 0),
+            field.point_at(field.cols() - 1, field.rows() - 1),
+        ]);
+        let largest = match &self.separation_field {
+            Some(field) => field.min_max().1.max(self.separation / 10.0),
+            None => self.separation,
+        };
+        let mut grid = SpatialHash::new(largest);
+
+        let inside = |p: Vec2| p.cmpge(min).all() && p.cmple(max).all();
+        let mut lines: Vec<Vec<Vec2>> = Vec::new();
+        // placed lines that may still seed others, with how far along their candidates we are
+        let mut queue: VecDeque<(usize, usize)> = VecDeque::new();
+        let mut seeds = seeds.iter().copied();
+
+        loop {
+            let candidate = match queue.front_mut() {
+                Some((line, cursor)) => match self.next_seed(&lines[*line], cursor, &grid, &inside)
+                {
+                    Some(seed) => seed,
+                    None => {
+                        queue.pop_front();
+                        continue;
+                    }
+                },
+                None => match seeds.next() {
+                    Some(seed) if inside(seed) && self.is_free(seed, &grid) => seed,
+                    Some(_) => continue,
+                    None => break,
+                },
+            };
+
+            let line = tracer.trace_with(field, candidate, |p, _| {
+                !inside(p) || grid.any_within(p, self.test * self.separation_at(p))
+            });
+            if line.len() < self.min_points {
+                continue;
+            }
+
+            line.iter().for_each(|p| {
+                grid.insert(*p);
+            });
+            queue.push_back((lines.len(), 0));
+            lines.push(line);
+        }
+
+        lines
+    }
+
+    // the next point beside the line with no other line nearby, candidates alternate
+    // left and right of every point; `cursor` remembers where to pick up next time
+    fn next_seed(
+        &self,
+        line: &[Vec2],
+        cursor: &mut usize,
+        grid: &SpatialHash,
+        inside: &impl Fn(Vec2) -> bool,
+    ) -> Option<Vec2> {
+        while *cursor < 2 * (line.len() - 1) {
+            let (n, side) = (
+                *cursor / 2,
+                if cursor.is_multiple_of(2) { 1.0 } else { -1.0 },
+            );
+            *cursor += 1;
+
+            let tangent = (line[n + 1] - line[n]).normalize_or_zero();
+            let normal = Vec2::new(-tangent.y, tangent.x) * self.separation_at(line[n]);
+            let candidate = line[n] + normal * side;
+            if inside(candidate) && self.is_free(candidate, grid) {
+                return Some(candidate);
+            }
+        }
+        None
+    }
+
+    // whether a seed keeps its distance to every placed line, with a little slack
+    // so that seeds exactly `separation` away from their parent line are accepted
+    fn is_free(&self, point: Vec2, grid: &SpatialHash) -> bool {
+        !grid.any_within(point, self.separation_at(point) * 0.99)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uniform(width: f32, height: f32) -> FlowField {
+        let mut field = FlowField::new(width, height, 10.0, 0.0);
+        field.cells_mut().fill(Vec2::X);
+        field
+    }
+
+    #[test]
+    fn lines_keep_their_separation() {
+        let field = uniform(200.0, 200.0);
+        let tracer = Tracer::new(1.0, 1000);
+        let lines = EvenlySpaced::new(20.0, 0.5).place(&field, &tracer, &[Vec2::splat(100.0)]);
+
+        // a uniform flow to the right fills the field with horizontal lines 20 apart
+        assert!(lines.len() >= 8, "{} lines", lines.len());
+        for (n, a) in lines.iter().enumerate() {
+            assert!(a.iter().all(|p| (p.y - a[0].y).abs() < 1e-3));
+            for b in &lines[n + 1..] {
+                assert!((a[0].y - b[0].y).abs() >= 10.0 - 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_field_places_nothing() {
+        let field = uniform(0.0, 0.0);
+        let tracer = Tracer::new(1.0, 100);
+        assert!(EvenlySpaced::new(5.0, 0.5)
+            .place(&field, &tracer, &[Vec2::ZERO])
+            .is_empty());
+    }
+}
@@ -0,0 +1,690 @@
+use crate::common::Seedable;
+use crate::forces::field::ForceField;
+use crate::utils::{perlin, PerlinNoise};
+use nannou::noise::NoiseFn;
+use nannou::prelude::Vec2;
+use std::cell::RefCell;
+use std::f64::consts;
+use std::fmt;
+
+// Force field defined by a small expression language, parsed at runtime.
+//
+//     angle = noise(x * 0.01, y * 0.01) * tau
+//     vx = -y; vy = x
+//     d = r * 0.05; angle = theta + pi / 2; magnitude = sin(d + t)
+//
+// A program is a list of assignments separated by `;` or newlines.
+// It must assign either `angle` (and optionally `magnitude`) or both `vx` and `vy`;
+// any other name becomes a local variable usable by the statements after it.
+//
+// variables : x, y (field indices), t (time), r, theta (polar, around `center`)
+// constants : pi, tau, e
+// operators : + - * / % ^ (power), unary -, parentheses
+// functions : sin cos tan asin acos atan atan2 sqrt abs exp ln log floor ceil fract sign
+//             min max pow mod clamp mix step smoothstep noise(x, y) noise(x, y, z)
+//
+// Lines starting with `#` are comments.
+pub struct ExprField {
+    pub strength: f64,
+    source: String,
+    program: Program,
+    center: [f64; 2],
+    noise: PerlinNoise,
+    seed: u32,
+}
+
+impl ExprField {
+    pub fn parse(source: &str, strength: f64, seed: u32) -> Result<Self, ExprError> {
+        let program = Parser::new(source).program()?;
+        Ok(ExprField {
+            strength,
+            source: source.to_string(),
+            program,
+            center: [0.0, 0.0],
+            noise: perlin(seed),
+            seed,
+        })
+    }
+
+    // origin (in field indices) for the polar variables r and theta
+    pub fn center(&mut self, i: f64, j: f64) -> &mut Self {
+        self.center = [i, j];
+        self
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    fn eval(&self, expr: &Expr, slots: &[f64]) -> f64 {
+        match expr {
+            Expr::Number(v) => *v,
+            Expr::Slot(n) => slots[*n],
+            Expr::Neg(a) => -self.eval(a, slots),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (self.eval(a, slots), self.eval(b, slots));
+                match op {
+                    Op::Add => a + b,
+                    Op::Sub => a - b,
+                    Op::Mul => a * b,
+                    Op::Div => a / b,
+                    Op::Rem => a.rem_euclid(b),
+                    Op::Pow => a.powf(b),
+                }
+            }
+            Expr::Call(func, args) => {
+                let mut values = [0.0; MAX_ARGS];
+                for (value, arg) in values.iter_mut().zip(args) {
+                    *value = self.eval(arg, slots);
+                }
+                func.apply(&values[..args.len()], &self.noise)
+            }
+        }
+    }
+}
+
+impl Seedable for ExprField {
+    fn set_seed(&mut self, seed: u32) -> &mut Self {
+        self.seed = seed;
+        self.noise = perlin(seed);
+        self
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+impl ForceField for ExprField {
+    fn get(&self, i: f64, j: f64) -> Vec2 {
+        self.get_at(i, j, 0.0)
+    }
+
+    fn get_at(&self, i: f64, j: f64, t: f64) -> Vec2 {
+        let (dx, dy) = (i - self.center[0], j - self.center[1]);
+        let v = SLOTS.with(|slots| {
+            let mut slots = slots.borrow_mut();
+            slots.clear();
+            slots.resize(self.program.slots, 0.0);
+            slots[..BUILTINS.len()].copy_from_slice(&[i, j, t, dx.hypot(dy), dy.atan2(dx)]);
+
+            for (slot, expr) in &self.program.statements {
+                slots[*slot] = self.eval(expr, &slots);
+            }
+
+            match self.program.output {
+                Output::Cartesian { vx, vy } => Vec2::new(slots[vx] as f32, slots[vy] as f32),
+                Output::Polar { angle, magnitude } => {
+                    let angle = slots[angle] as f32;
+                    let magnitude = magnitude.map_or(1.0, |m| slots[m]) as f32;
+                    Vec2::new(angle.cos(), angle.sin()) * magnitude
+                }
+            }
+        });
+
+        if v.is_finite() {
+            v * self.strength as f32
+        } else {
+            Vec2::ZERO
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExprError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    source_line: String,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} (line {}, column {})",
+            self.message, self.line, self.column
+        )?;
+        writeln!(f, "  {}", self.source_line)?;
+        write!(f, "  {}^", " ".repeat(self.column.saturating_sub(1)))
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+// x, y, t, r, theta occupy the first slots, locals follow
+const BUILTINS: [&str; 5] = ["x", "y", "t", "r", "theta"];
+
+// the most arguments any function takes
+const MAX_ARGS: usize = 3;
+
+thread_local! {
+    // variable values while evaluating a sample, reused so `par_merge` does not allocate per cell
+    static SLOTS: RefCell<Vec<f64>> = const { RefCell::new(Vec::new()) };
+}
+
+struct Program {
+    statements: Vec<(usize, Expr)>,
+    slots: usize,
+    output: Output,
+}
+
+enum Output {
+    Cartesian {
+        vx: usize,
+        vy: usize,
+    },
+    Polar {
+        angle: usize,
+        magnitude: Option<usize>,
+    },
+}
+
+enum Expr {
+    Number(f64),
+    Slot(usize),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+}
+
+#[derive(Clone, Copy)]
+enum Func {
+    Unary(fn(f64) -> f64),
+    Binary(fn(f64, f64) -> f64),
+    Clamp,
+    Mix,
+    SmoothStep,
+    Noise,
+}
+
+impl Func {
+    fn lookup(name: &str) -> Option<(Func, &'static [usize])> {
+        let unary = |f| Some((Func::Unary(f), &[1usize][..]));
+        let binary = |f| Some((Func::Binary(f), &[2usize][..]));
+        match name {
+            "sin" => unary(f64::sin),
+            "cos" => unary(f64::cos),
+            "tan" => unary(f64::tan),
+            "asin" => unary(f64::asin),
+            "acos" => unary(f64::acos),
+            "atan" => unary(f64::atan),
+            "sqrt" => unary(f64::sqrt),
+            "abs" => unary(f64::abs),
+            "exp" => unary(f64::exp),
+            "ln" => unary(f64::ln),
+            "log" => unary(f64::log10),
+            "floor" => unary(f64::floor),
+            "ceil" => unary(f64::ceil),
+            "fract" => unary(f64::fract),
+            "sign" => unary(f64::signum),
+            "atan2" => binary(f64::atan2),
+            "min" => binary(f64::min),
+            "max" => binary(f64::max),
+            "pow" => binary(f64::powf),
+            "mod" => binary(f64::rem_euclid),
+            "step" => binary(|edge, v| if v < edge { 0.0 } else { 1.0 }),
+            "clamp" => Some((Func::Clamp, &[3][..])),
+            "mix" => Some((Func::Mix, &[3][..])),
+            "smoothstep" => Some((Func::SmoothStep, &[3][..])),
+            "noise" => Some((Func::Noise, &[2, 3][..])),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, args: &[f64], noise: &PerlinNoise) -> f64 {
+        match self {
+            Func::Unary(f) => f(args[0]),
+            Func::Binary(f) => f(args[0], args[1]),
+            Func::Clamp => args[0].max(args[1]).min(args[2]),
+            Func::Mix => args[0] + (args[1] - args[0]) * args[2],
+            Func::SmoothStep => {
+                let t = ((args[2] - args[0]) / (args[1] - args[0])).clamp(0.0, 1.0);
+                t * t * (3.0 - 2.0 * t)
+            }
+            Func::Noise => match args {
+                [x, y] => noise.get([*x, *y]),
+                _ => noise.get([args[0], args[1], args[2]]),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Symbol(char),
+    Separator,
+    End,
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    names: Vec<String>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Self {
+        Parser {
+            source,
+            tokens: Vec::new(),
+            pos: 0,
+            names: BUILTINS.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn error(&self, offset: usize, message: impl Into<String>) -> ExprError {
+        let before = &self.source[..offset.min(self.source.len())];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |n| n + 1);
+        let column = before[line_start..].chars().count() + 1;
+        let source_line = self.source[line_start..]
+            .lines()
+            .next()
+            .unwrap_or("")
+            .to_string();
+
+        ExprError {
+            line,
+            column,
+            message: message.into(),
+            source_line,
+        }
+    }
+
+    fn tokenize(&mut self) -> Result<(), ExprError> {
+        let src = self.source;
+        let mut chars = src.char_indices().peekable();
+
+        while let Some(&(at, c)) = chars.peek() {
+            if c == '#' {
+                while matches!(chars.peek(), Some(&(_, c)) if c != '\n') {
+                    chars.next();
+                }
+            } else if c == ';' || c == '\n' {
+                self.tokens.push((Token::Separator, at));
+                chars.next();
+            } else if c.is_whitespace() {
+                chars.next();
+            } else if c.is_ascii_digit() || c == '.' {
+                let mut end = at;
+                while let Some(&(n, c)) = chars.peek() {
+                    let exponent_sign = (c == '+' || c == '-')
+                        && matches!(src[..n].chars().last(), Some('e' | 'E'));
+                    if c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || exponent_sign {
+                        end = n + c.len_utf8();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let text = &src[at..end];
+                let value = text
+                    .parse()
+                    .map_err(|_| self.error(at, format!("invalid number '{}'", text)))?;
+                self.tokens.push((Token::Number(value), at));
+            } else if c.is_alphabetic() || c == '_' {
+                let mut end = at;
+                while let Some(&(n, c)) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        end = n + c.len_utf8();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                self.tokens
+                    .push((Token::Ident(src[at..end].to_string()), at));
+            } else if "+-*/%^(),=".contains(c) {
+                self.tokens.push((Token::Symbol(c), at));
+                chars.next();
+            } else {
+                return Err(self.error(at, format!("unexpected character '{}'", c)));
+            }
+        }
+
+        self.tokens.push((Token::End, src.len()));
+        Ok(())
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens[self.pos].1
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].0.clone();
+        if token != Token::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), ExprError> {
+        if *self.peek() == Token::Symbol(symbol) {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.error(self.offset(), format!("expected '{}'", symbol)))
+        }
+    }
+
+    fn program(mut self) -> Result<Program, ExprError> {
+        self.tokenize()?;
+        let mut statements = Vec::new();
+
+        loop {
+            while *self.peek() == Token::Separator {
+                self.next();
+            }
+            if *self.peek() == Token::End {
+                break;
+            }
+
+            let at = self.offset();
+            let name = match self.next() {
+                Token::Ident(name) => name,
+                _ => return Err(self.error(at, "expected an assignment like 'angle = ...'")),
+            };
+            if BUILTINS.contains(&name.as_str()) || constant(&name).is_some() {
+                return Err(self.error(at, format!("cannot assign to built-in '{}'", name)));
+            }
+            self.expect('=')?;
+
+            let expr = self.expression()?;
+            let slot = self.slot_for(&name);
+            statements.push((slot, expr));
+
+            match self.peek() {
+                Token::Separator | Token::End => {}
+                _ => return Err(self.error(self.offset(), "expected ';' or a new line")),
+            }
+        }
+
+        let find = |name: &str| self.names.iter().position(|n| n == name);
+        let output = match (find("vx"), find("vy"), find("angle")) {
+            (Some(vx), Some(vy), _) => Output::Cartesian { vx, vy },
+            (_, _, Some(angle)) => Output::Polar {
+                angle,
+                magnitude: find("magnitude"),
+            },
+            (Some(_), None, None) => {
+                return Err(self.error(self.source.len(), "'vx' is assigned but 'vy' is not"))
+            }
+            (None, Some(_), None) => {
+                return Err(self.error(self.source.len(), "'vy' is assigned but 'vx' is not"))
+            }
+            (None, None, None) => {
+                return Err(self.error(
+                    self.source.len(),
+                    "assign either 'angle' or both 'vx' and 'vy'",
+                ))
+            }
+        };
+
+        Ok(Program {
+            statements,
+            slots: self.names.len(),
+            output,
+        })
+    }
+
+    fn slot_for(&mut self, name: &str) -> usize {
+        match self.names.iter().position(|n| n == name) {
+            Some(slot) => slot,
+            None => {
+                self.names.push(name.to_string());
+                self.names.len() - 1
+            }
+        }
+    }
+
+    // sum := product (('+' | '-') product)*
+    fn expression(&mut self) -> Result<Expr, ExprError> {
+        let mut lhs = self.product()?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol('+') => Op::Add,
+                Token::Symbol('-') => Op::Sub,
+                _ => return Ok(lhs),
+            };
+            self.next();
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.product()?));
+        }
+    }
+
+    // product := unary (('*' | '/' | '%') unary)*
+    fn product(&mut self) -> Result<Expr, ExprError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol('*') => Op::Mul,
+                Token::Symbol('/') => Op::Div,
+                Token::Symbol('%') => Op::Rem,
+                _ => return Ok(lhs),
+            };
+            self.next();
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+    }
+
+    // unary := '-' unary | power
+    fn unary(&mut self) -> Result<Expr, ExprError> {
+        if *self.peek() == Token::Symbol('-') {
+            self.next();
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    // power := atom ('^' unary)?, right associative so 2^3^2 == 2^9
+    fn power(&mut self) -> Result<Expr, ExprError> {
+        let base = self.atom()?;
+        if *self.peek() == Token::Symbol('^') {
+            self.next();
+            return Ok(Expr::Binary(
+                Op::Pow,
+                Box::new(base),
+                Box::new(self.unary()?),
+            ));
+        }
+        Ok(base)
+    }
+
+    // atom := number | name | name '(' args ')' | '(' expression ')'
+    fn atom(&mut self) -> Result<Expr, ExprError> {
+        let at = self.offset();
+        match self.next() {
+            Token::Number(v) => Ok(Expr::Number(v)),
+            Token::Symbol('(') => {
+                let expr = self.expression()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Token::Ident(name) if *self.peek() == Token::Symbol('(') => self.call(&name, at),
+            Token::Ident(name) => {
+                if let Some(value) = constant(&name) {
+                    return Ok(Expr::Number(value));
+                }
+                match self.names.iter().position(|n| *n == name) {
+                    Some(slot) => Ok(Expr::Slot(slot)),
+                    None if Func::lookup(&name).is_some() => Err(self.error(
+                        at,
+                        format!("'{}' is a function, call it like {}(...)", name, name),
+                    )),
+                    None => Err(self.error(at, format!("unknown variable '{}'", name))),
+                }
+            }
+            Token::End | Token::Separator => {
+                Err(self.error(at, "expected a value, found end of expression"))
+            }
+            Token::Symbol(c) => Err(self.error(at, format!("expected a value, found '{}'", c))),
+        }
+    }
+
+    fn call(&mut self, name: &str, at: usize) -> Result<Expr, ExprError> {
+        let (func, arity) = Func::lookup(name)
+            .ok_or_else(|| self.error(at, format!("unknown function '{}'", name)))?;
+        self.expect('(')?;
+
+        let mut args = Vec::new();
+        if *self.peek() != Token::Symbol(')') {
+            loop {
+                args.push(self.expression()?);
+                if *self.peek() != Token::Symbol(',') {
+                    break;
+                }
+                self.next();
+            }
+        }
+        self.expect(')')?;
+
+        if !arity.contains(&args.len()) {
+            let expected: Vec<String> = arity.iter().map(|n| n.to_string()).collect();
+            return Err(self.error(
+                at,
+                format!(
+                    "{}() takes {} argument(s), got {}",
+                    name,
+                    expected.join(" or "),
+                    args.len()
+                ),
+            ));
+        }
+
+        Ok(Expr::Call(func, args))
+    }
+}
+
+fn constant(name: &str) -> Option<f64> {
+    match name {
+        "pi" => Some(consts::PI),
+        "tau" => Some(consts::TAU),
+        "e" => Some(consts::E),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str, i: f64, j: f64, t: f64) -> Vec2 {
+        ExprField::parse(source, 1.0, 0).unwrap().get_at(i, j, t)
+    }
+
+    fn error(source: &str) -> (usize, usize, String) {
+        let err = ExprField::parse(source, 1.0, 0).err().unwrap();
+        (err.line, err.column, err.message)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(
+            eval(
+                "vx = 1 + 2 * 3 ^ 2; vy = (1 + 2) * 3 - 8 / 4",
+                0.0,
+                0.0,
+                0.0
+            ),
+            Vec2::new(19.0, 7.0)
+        );
+        assert_eq!(
+            eval("vx = 2 ^ 3 ^ 2; vy = 7 % 4 * 2", 0.0, 0.0, 0.0),
+            Vec2::new(512.0, 6.0)
+        );
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(
+            eval("vx = -2 ^ 2; vy = - -3 - -1", 0.0, 0.0, 0.0),
+            Vec2::new(-4.0, 4.0)
+        );
+        assert_eq!(
+            eval("vx = -x; vy = 2 * -y", 3.0, 4.0, 0.0),
+            Vec2::new(-3.0, -8.0)
+        );
+    }
+
+    #[test]
+    fn function_calls() {
+        let v = eval(
+            "vx = max(1, 2) + clamp(5, 0, 3); vy = atan2(1, 0)",
+            0.0,
+            0.0,
+            0.0,
+        );
+        assert_eq!(v.x, 5.0);
+        assert!((v.y - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+        assert_eq!(
+            eval(
+                "vx = mix(2, 4, 0.25); vy = smoothstep(0, 2, 1)",
+                0.0,
+                0.0,
+                0.0
+            ),
+            Vec2::new(2.5, 0.5)
+        );
+
+        let noise = eval("vx = noise(x, y); vy = noise(x, y, t)", 0.3, 0.7, 0.2);
+        assert_eq!(
+            noise,
+            eval("vx = noise(x, y); vy = noise(x, y, t)", 0.3, 0.7, 0.2)
+        );
+        assert!(noise.x.abs() <= 1.0 && noise.y.abs() <= 1.0);
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(
+            eval("d = x + y\nvx = d * 2; vy = t # time", 1.0, 2.0, 3.0),
+            Vec2::new(6.0, 3.0)
+        );
+
+        let v = eval("angle = theta; magnitude = r", 3.0, 4.0, 0.0);
+        assert!((v - Vec2::new(3.0, 4.0)).length() < 1e-5);
+
+        let mut field = ExprField::parse("vx = r; vy = 0", 2.0, 0).unwrap();
+        field.center(1.0, 1.0);
+        assert_eq!(field.get(4.0, 5.0), Vec2::new(10.0, 0.0));
+    }
+
+    #[test]
+    fn error_positions() {
+        assert_eq!(error("angle = sin(x"), (1, 14, "expected ')'".to_string()));
+        assert_eq!(
+            error("a = 1\nangle = q * 2"),
+            (2, 9, "unknown variable 'q'".to_string())
+        );
+        assert_eq!(
+            error("angle = min(1)"),
+            (1, 9, "min() takes 2 argument(s), got 1".to_string())
+        );
+        assert_eq!(
+            error("angle = 1 $ 2"),
+            (1, 11, "unexpected character '$'".to_string())
+        );
+        assert_eq!(
+            error("x = 1; angle = 0"),
+            (1, 1, "cannot assign to built-in 'x'".to_string())
+        );
+        assert_eq!(error("vx = 1").2, "'vx' is assigned but 'vy' is not");
+    }
+}
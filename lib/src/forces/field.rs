@@ -1,18 +1,30 @@
+use crate::common::Seedable;
+use crate::utils::{hash2_unit, perlin, PerlinNoise};
 use nannou::{
     math::map_range,
-    noise::{NoiseFn, Seedable},
+    noise::NoiseFn,
     prelude::{Vec2, TAU},
-    rand::random_range,
 };
 
 pub trait ForceField {
     fn get(&self, i: f64, j: f64) -> Vec2;
+
+    // force at (i, j) at time t
+    // fields that do not change over time ignore t and fall back to `get`
+    fn get_at(&self, i: f64, j: f64, _t: f64) -> Vec2 {
+        self.get(i, j)
+    }
 }
 
+// Fields that use randomness take it from their seed, never from the global rng,
+// so that merging them into a `FlowField` stays reproducible for a given seed.
 pub struct PerlinField {
     pub strength: f64,
     pub scale: f64,
-    pub noise: nannou::noise::Perlin,
+    pub noise: PerlinNoise,
+    seed: u32,
+    // time scale of the third noise dimension, None for a field that does not change
+    speed: Option<f64>,
 }
 
 impl PerlinField {
@@ -20,14 +32,35 @@ impl PerlinField {
         PerlinField {
             scale,
             strength,
-            noise: nannou::noise::Perlin::new().set_seed(seed),
+            noise: perlin(seed),
+            seed,
+            speed: None,
         }
     }
+
+    // make the noise evolve with time, `t * speed` becomes its third dimension.
+    // 3-d noise is a different field from the 2-d one, so `get_at` no longer
+    // matches `get`, even at t = 0
+    pub fn animate(&mut self, speed: f64) -> &mut Self {
+        self.speed = Some(speed);
+        self
+    }
 }
 
-impl ForceField for PerlinField {
-    fn get(&self, i: f64, j: f64) -> Vec2 {
-        let noise = self.noise.get([i * self.scale, j * self.scale]);
+impl Seedable for PerlinField {
+    fn set_seed(&mut self, seed: u32) -> &mut Self {
+        self.seed = seed;
+        self.noise = perlin(seed);
+        self
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+impl PerlinField {
+    fn noise_to_force(&self, noise: f64) -> Vec2 {
         let noise = map_range(noise, 0.0, 1.0, 0.0, TAU);
         Vec2::new(
             noise.cos() * self.strength as f32,
@@ -36,10 +69,32 @@ impl ForceField for PerlinField {
     }
 }
 
+impl ForceField for PerlinField {
+    fn get(&self, i: f64, j: f64) -> Vec2 {
+        self.noise_to_force(self.noise.get([i * self.scale, j * self.scale]))
+    }
+
+    // static unless `animate`d, then time is the third noise dimension
+    // and the field evolves smoothly with t
+    fn get_at(&self, i: f64, j: f64, t: f64) -> Vec2 {
+        match self.speed {
+            Some(speed) => {
+                let point = [i * self.scale, j * self.scale, t * speed];
+                self.noise_to_force(self.noise.get(point))
+            }
+            None => self.get(i, j),
+        }
+    }
+}
+
+// Path of a moving attractor: maps time to a position in field indices.
+pub type AttractorPath = Box<dyn Fn(f64) -> [f64; 2] + Send + Sync>;
+
 pub struct AttractorField {
     strength: f64,
     i: f64,
     j: f64,
+    path: Option<AttractorPath>,
 }
 
 impl AttractorField {
@@ -48,18 +103,41 @@ impl AttractorField {
             i: i as f64,
             j: j as f64,
             strength,
+            path: None,
         }
     }
-}
 
-impl ForceField for AttractorField {
-    fn get(&self, i: f64, j: f64) -> Vec2 {
-        let dx = self.i - i;
-        let dy = self.j - j;
+    // make the attractor follow a path over time, e.g. a circle:
+    // `.moving(|t| [50.0 + 10.0 * t.cos(), 50.0 + 10.0 * t.sin()])`
+    // `get` (without time) still uses the fixed position given to `new`
+    pub fn moving(&mut self, path: impl Fn(f64) -> [f64; 2] + Send + Sync + 'static) -> &mut Self {
+        self.path = Some(Box::new(path));
+        self
+    }
+
+    fn force_towards(&self, ai: f64, aj: f64, i: f64, j: f64) -> Vec2 {
+        let dx = ai - i;
+        let dy = aj - j;
         let distance = (dx * dx + dy * dy).sqrt();
         let decay = (self.strength / distance.max(1.0).powf(1.0 / 3.0)) as f32;
         let angle = dy.atan2(dx) as f32;
-        return Vec2::new(angle.cos() * decay, angle.sin() * decay);
+        Vec2::new(angle.cos() * decay, angle.sin() * decay)
+    }
+}
+
+impl ForceField for AttractorField {
+    fn get(&self, i: f64, j: f64) -> Vec2 {
+        self.force_towards(self.i, self.j, i, j)
+    }
+
+    fn get_at(&self, i: f64, j: f64, t: f64) -> Vec2 {
+        match &self.path {
+            Some(path) => {
+                let [ai, aj] = path(t);
+                self.force_towards(ai, aj, i, j)
+            }
+            None => self.get(i, j),
+        }
     }
 }
 
@@ -80,22 +158,68 @@ impl ForceField for GradientField {
     }
 }
 
+// Random direction per lattice point, derived from a hash of (i, j, seed).
+// Sampling the same point with the same seed always returns the same vector.
+//
+// smoothing : radius (in cells) over which neighbouring directions are blended,
+//             0.0 gives white noise, larger values give softer, blotchy regions
 pub struct RandomField {
     pub strength: f64,
+    pub smoothing: f64,
+    seed: u32,
 }
 
 impl RandomField {
-    pub fn new(strength: f64) -> Self {
-        RandomField { strength }
+    pub fn new(strength: f64, seed: u32) -> Self {
+        RandomField {
+            strength,
+            smoothing: 0.0,
+            seed,
+        }
+    }
+
+    pub fn smoothing(&mut self, radius: f64) -> &mut Self {
+        self.smoothing = radius.max(0.0);
+        self
+    }
+
+    fn direction(&self, i: i64, j: i64) -> Vec2 {
+        let angle = hash2_unit(i, j, self.seed) * TAU;
+        Vec2::new(angle.cos(), angle.sin())
+    }
+}
+
+impl Seedable for RandomField {
+    fn set_seed(&mut self, seed: u32) -> &mut Self {
+        self.seed = seed;
+        self
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed
     }
 }
 
 impl ForceField for RandomField {
-    fn get(&self, _i: f64, _j: f64) -> Vec2 {
-        let angle = random_range(0.0, TAU as f32);
-        Vec2::new(
-            angle.cos() * self.strength as f32,
-            angle.sin() * self.strength as f32,
-        )
+    fn get(&self, i: f64, j: f64) -> Vec2 {
+        if self.smoothing <= 0.0 {
+            return self.direction(i.round() as i64, j.round() as i64) * self.strength as f32;
+        }
+
+        // tent-weighted average of lattice directions within the smoothing radius
+        let r = self.smoothing;
+        let mut sum = Vec2::ZERO;
+        for ii in (i - r).ceil() as i64..=(i + r).floor() as i64 {
+            for jj in (j - r).ceil() as i64..=(j + r).floor() as i64 {
+                let (dx, dy) = (ii as f64 - i, jj as f64 - j);
+                let weight = 1.0 - (dx * dx + dy * dy).sqrt() / r;
+                if weight > 0.0 {
+                    sum += self.direction(ii, jj) * weight as f32;
+                }
+            }
+        }
+
+        // averaging shrinks the vector, renormalize so strength means the same thing
+        sum.normalize_or_zero() * self.strength as f32
     }
 }
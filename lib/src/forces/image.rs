@@ -0,0 +1,249 @@
+use crate::forces::field::ForceField;
+use crate::utils::blur;
+use nannou::image::error::{ImageError, ParameterError, ParameterErrorKind};
+use nannou::image::{self, DynamicImage, ImageResult};
+use nannou::prelude::{Vec2, PI};
+use std::ops::{Add, Mul, Sub};
+use std::path::Path;
+
+// How an image is turned into vectors
+//
+// Gradient  : luminance gradient (Sobel), points from dark to bright
+// Tangent   : gradient rotated by 90°, i.e. flows along edges
+// Structure : dominant edge orientation from the smoothed structure tensor,
+//             stable in noisy regions; magnitude is the local coherence
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageMode {
+    Gradient,
+    Tangent,
+    Structure,
+}
+
+// Force field sampled from a bitmap.
+//
+// By default one pixel maps to one field cell with the image's top-left corner at (0, 0).
+// Use `fit` to stretch the image over a range of field indices instead,
+// e.g. `FlowField::visible_bounds()` to cover the window. Outside the image the force is zero.
+pub struct ImageField {
+    pub strength: f64,
+    mode: ImageMode,
+    blur: f32,
+    normalize: bool,
+
+    width: usize,
+    height: usize,
+    luma: Vec<f32>,
+    vectors: Vec<Vec2>,
+    raster: Raster,
+}
+
+impl ImageField {
+    // fails for an image without pixels
+    pub fn new(image: &DynamicImage, mode: ImageMode, strength: f64) -> ImageResult<Self> {
+        let luma = image.to_luma8();
+        let (width, height) = (luma.width() as usize, luma.height() as usize);
+        if width == 0 || height == 0 {
+            return Err(empty_image());
+        }
+        let luma = luma
+            .into_raw()
+            .into_iter()
+            .map(|v| v as f32 / 255.0)
+            .collect();
+
+        let mut field = ImageField {
+            strength,
+            mode,
+            blur: 0.0,
+            normalize: true,
+            width,
+            height,
+            luma,
+            vectors: Vec::new(),
+            raster: Raster::new(width, height),
+        };
+        field.bake();
+        Ok(field)
+    }
+
+    pub fn open(path: impl AsRef<Path>, mode: ImageMode, strength: f64) -> ImageResult<Self> {
+        let image = image::open(path)?;
+        Self::new(&image, mode, strength)
+    }
+
+    // gaussian blur radius (in pixels) applied to the luminance before
+    // taking gradients, and to the tensor in `Structure` mode
+    pub fn blur(&mut self, radius: f32) -> &mut Self {
+        self.blur = radius.max(0.0);
+        self.bake();
+        self
+    }
+
+    // true  : every non-zero vector has unit length (times strength)
+    // false : magnitudes are kept relative to the strongest vector in the image
+    pub fn normalize(&mut self, normalize: bool) -> &mut Self {
+        self.normalize = normalize;
+        self.bake();
+        self
+    }
+
+    pub fn mode(&mut self, mode: ImageMode) -> &mut Self {
+        self.mode = mode;
+        self.bake();
+        self
+    }
+
+    // stretch the image over the field indices [from, to)
+    pub fn fit(&mut self, bounds: [[usize; 2]; 2]) -> &mut Self {
+        self.raster.fit(bounds);
+        self
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn bake(&mut self) {
+        let (w, h) = (self.width, self.height);
+        let luma = blur(&self.luma, w, h, self.blur);
+        let (gx, gy) = sobel(&luma, w, h);
+
+        let mut vectors: Vec<Vec2> = match self.mode {
+            ImageMode::Gradient => gx.iter().zip(&gy).map(|(x, y)| Vec2::new(*x, *y)).collect(),
+            ImageMode::Tangent => gx
+                .iter()
+                .zip(&gy)
+                .map(|(x, y)| Vec2::new(-*y, *x))
+                .collect(),
+            ImageMode::Structure => {
+                let jxx: Vec<f32> = gx.iter().map(|x| x * x).collect();
+                let jxy: Vec<f32> = gx.iter().zip(&gy).map(|(x, y)| x * y).collect();
+                let jyy: Vec<f32> = gy.iter().map(|y| y * y).collect();
+                let radius = self.blur.max(1.0);
+                let (jxx, jxy, jyy) = (
+                    blur(&jxx, w, h, radius),
+                    blur(&jxy, w, h, radius),
+                    blur(&jyy, w, h, radius),
+                );
+
+                (0..w * h)
+                    .map(|n| {
+                        let (a, b, c) = (jxx[n], jxy[n], jyy[n]);
+                        let root = ((a - c) * (a - c) + 4.0 * b * b).sqrt();
+                        let (l1, l2) = ((a + c + root) / 2.0, (a + c - root) / 2.0);
+                        if l1 + l2 <= f32::EPSILON {
+                            return Vec2::ZERO;
+                        }
+
+                        // gradient orientation, rotated to run along the edge
+                        let angle = 0.5 * (2.0 * b).atan2(a - c) + PI / 2.0;
+                        let coherence = (l1 - l2) / (l1 + l2);
+                        Vec2::new(angle.cos(), angle.sin()) * coherence
+                    })
+                    .collect()
+            }
+        };
+
+        if self.normalize {
+            vectors.iter_mut().for_each(|v| *v = v.normalize_or_zero());
+        } else {
+            let max = vectors.iter().map(|v| v.length()).fold(0.0, f32::max);
+            if max > 0.0 {
+                vectors.iter_mut().for_each(|v| *v /= max);
+            }
+        }
+
+        self.vectors = vectors;
+    }
+}
+
+impl ForceField for ImageField {
+    fn get(&self, i: f64, j: f64) -> Vec2 {
+        self.raster
+            .sample(&self.vectors, i, j)
+            .map_or(Vec2::ZERO, |v| v * self.strength as f32)
+    }
+}
+
+// Field indices laid over a `width` x `height` raster of pixels or imported cells,
+// one raster cell per field index from (0, 0) unless `fit` stretches it over a range
+// of indices. Shared by the image field, image mask and grid field.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Raster {
+    width: usize,
+    height: usize,
+    origin: [f64; 2],
+    scale: [f64; 2],
+}
+
+impl Raster {
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        Raster {
+            width,
+            height,
+            origin: [0.0, 0.0],
+            scale: [1.0, 1.0],
+        }
+    }
+
+    // stretch the raster over the field indices [from, to)
+    pub(crate) fn fit(&mut self, bounds: [[usize; 2]; 2]) {
+        let [from, to] = bounds;
+        let cols = (to[0].saturating_sub(from[0])).max(1) as f64;
+        let rows = (to[1].saturating_sub(from[1])).max(1) as f64;
+        self.origin = [from[0] as f64, from[1] as f64];
+        self.scale = [self.width as f64 / cols, self.height as f64 / rows];
+    }
+
+    // bilinear interpolation of the row-major `values` between the four raster cells
+    // around field indices (i, j), None outside the raster
+    pub(crate) fn sample<T>(&self, values: &[T], i: f64, j: f64) -> Option<T>
+    where
+        T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+    {
+        let x = (i - self.origin[0]) * self.scale[0];
+        let y = (j - self.origin[1]) * self.scale[1];
+        let (last_x, last_y) = (self.width.checked_sub(1)?, self.height.checked_sub(1)?);
+        if !(0.0..=last_x as f64).contains(&x) || !(0.0..=last_y as f64).contains(&y) {
+            return None;
+        }
+
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(last_x), (y0 + 1).min(last_y));
+        let (tx, ty) = ((x - x0 as f64) as f32, (y - y0 as f64) as f32);
+
+        let at = |x: usize, y: usize| values[y * self.width + x];
+        let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * tx;
+        let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * tx;
+        Some(top + (bottom - top) * ty)
+    }
+}
+
+pub(crate) fn empty_image() -> ImageError {
+    ImageError::Parameter(ParameterError::from_kind(
+        ParameterErrorKind::DimensionMismatch,
+    ))
+}
+
+// horizontal and vertical Sobel derivatives of a row-major buffer, edges clamped
+fn sobel(values: &[f32], w: usize, h: usize) -> (Vec<f32>, Vec<f32>) {
+    let at = |x: isize, y: isize| {
+        let x = x.clamp(0, w as isize - 1) as usize;
+        let y = y.clamp(0, h as isize - 1) as usize;
+        values[y * w + x]
+    };
+
+    let mut gx = vec![0.0; w * h];
+    let mut gy = vec![0.0; w * h];
+    for y in 0..h as isize {
+        for x in 0..w as isize {
+            let n = y as usize * w + x as usize;
+            gx[n] = (at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1))
+                - (at(x - 1, y - 1) + 2.0 * at(x - 1, y) + at(x - 1, y + 1));
+            gy[n] = (at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1))
+                - (at(x - 1, y - 1) + 2.0 * at(x, y - 1) + at(x + 1, y - 1));
+        }
+    }
+
+    (gx, gy)
+}
@@ -3,14 +3,18 @@ use nannou::glam::Vec2;
 use nannou::math::map_range;
 use nannou::prelude::pt2;
 use nannou::{color::Rgba, Draw};
+use rayon::prelude::*;
 
 pub struct FlowField {
     seed: u32,
+    // free-form description of how the field was built, stored when saving
+    recipe: String,
     cols: usize,
     rows: usize,
     resolution: f32,
     extend: f32,
-    field: Vec<Vec<Vec2>>,
+    // row-major: the vector of cell (i, j) is at `j * cols + i`
+    field: Vec<Vec2>,
 
     width: f32,
     height: f32,
@@ -35,25 +39,18 @@ impl FlowField {
     pub const DEFAULT_SEED: u32 = 0;
 
     pub fn new(w: f32, h: f32, resolution: f32, extend: f32) -> Self {
-        let cols = f32::ceil(w * (1.0 + 2.0 * extend) / resolution) as usize;
-        let rows = f32::ceil(h * (1.0 + 2.0 * extend) / resolution) as usize;
+        let (cols, rows) = Self::grid_size(w, h, resolution, extend);
 
         let _left_x = -w * extend;
         let _right_x = w * (1.0 + extend);
         let _top_y = -h * extend;
         let _bottom_y = h * (1.0 + extend);
 
-        let mut field = Vec::with_capacity(cols);
-        for _i in 0..cols {
-            let mut row = Vec::with_capacity(rows);
-            for _j in 0..rows {
-                row.push(Vec2::new(0.0, 0.0));
-            }
-            field.push(row);
-        }
+        let field = vec![Vec2::new(0.0, 0.0); cols * rows];
 
         FlowField {
             seed: Self::DEFAULT_SEED,
+            recipe: String::new(),
             field,
             cols,
             rows,
@@ -69,56 +66,190 @@ impl FlowField {
         }
     }
 
-    pub fn zero(&mut self) -> &mut Self {
-        for i in 0..self.cols {
-            for j in 0..self.rows {
-                self.field[i][j] = Vec2::new(0.0, 0.0);
-            }
-        }
+    // cols and rows of a field built with `new(w, h, resolution, extend)`
+    pub fn grid_size(w: f32, h: f32, resolution: f32, extend: f32) -> (usize, usize) {
+        (
+            f32::ceil(w * (1.0 + 2.0 * extend) / resolution) as usize,
+            f32::ceil(h * (1.0 + 2.0 * extend) / resolution) as usize,
+        )
+    }
 
+    pub fn zero(&mut self) -> &mut Self {
+        self.field.fill(Vec2::new(0.0, 0.0));
         self
     }
 
+    // a new, zeroed field for a different size, keeping seed and recipe
     pub fn reset(&self, w: f32, h: f32) -> Self {
-        Self::new(w, h, self.resolution, self.extend)
+        let mut field = Self::new(w, h, self.resolution, self.extend);
+        field.seed = self.seed;
+        field.recipe = self.recipe.clone();
+        field
+    }
+
+    pub fn set_recipe(&mut self, recipe: impl Into<String>) -> &mut Self {
+        self.recipe = recipe.into();
+        self
+    }
+
+    pub fn recipe(&self) -> &str {
+        &self.recipe
     }
 
     pub fn merge(&mut self, force: &impl ForceField) -> &mut Self {
-        for i in 0..self.cols {
-            for j in 0..self.rows {
-                self.field[i][j] += force.get(i as f64, j as f64);
-            }
+        for (n, v) in self.field.iter_mut().enumerate() {
+            let (i, j) = (n % self.cols, n / self.cols);
+            *v += force.get(i as f64, j as f64);
+        }
+
+        self
+    }
+
+    // bake the field at time t, reusing the existing grid
+    // chain after `zero()` each frame to animate: `field.zero().merge_at(&perlin, t)`
+    pub fn merge_at(&mut self, force: &impl ForceField, t: f64) -> &mut Self {
+        for (n, v) in self.field.iter_mut().enumerate() {
+            let (i, j) = (n % self.cols, n / self.cols);
+            *v += force.get_at(i as f64, j as f64, t);
+        }
+
+        self
+    }
+
+    // same as `merge`, with rows evaluated in parallel on the rayon thread pool
+    // worth it for fine resolutions or expensive fields (images, paths, expressions)
+    pub fn par_merge(&mut self, force: &(impl ForceField + Sync)) -> &mut Self {
+        // a field for a zero-size window has no cells, and chunks of 0 would panic
+        if self.field.is_empty() {
+            return self;
+        }
+        self.field
+            .par_chunks_mut(self.cols)
+            .enumerate()
+            .for_each(|(j, row)| {
+                for (i, v) in row.iter_mut().enumerate() {
+                    *v += force.get(i as f64, j as f64);
+                }
+            });
+
+        self
+    }
+
+    // same as `merge_at`, with rows evaluated in parallel
+    pub fn par_merge_at(&mut self, force: &(impl ForceField + Sync), t: f64) -> &mut Self {
+        // a field for a zero-size window has no cells, and chunks of 0 would panic
+        if self.field.is_empty() {
+            return self;
         }
+        self.field
+            .par_chunks_mut(self.cols)
+            .enumerate()
+            .for_each(|(j, row)| {
+                for (i, v) in row.iter_mut().enumerate() {
+                    *v += force.get_at(i as f64, j as f64, t);
+                }
+            });
 
         self
     }
 
     pub fn force_at(&self, point: [f32; 2]) -> Vec2 {
+        if self.field.is_empty() {
+            return Vec2::ZERO;
+        }
         let [x, y] = self.index_at(point);
-        self.field[x as usize][y as usize]
+        self.cell(x, y)
     }
 
     pub fn index_at(&self, point: [f32; 2]) -> [usize; 2] {
         let x = (point[0] - self._left_x) / self.resolution;
         let y = (point[1] - self._top_y) / self.resolution;
 
-        let x = x.max(0.0).min(self._right_x - self.resolution / 2.0);
-        let y = y.max(0.0).min(self._bottom_y - self.resolution / 2.0);
+        let x = x.max(0.0).min(self.cols.saturating_sub(1) as f32);
+        let y = y.max(0.0).min(self.rows.saturating_sub(1) as f32);
 
         [x as usize, y as usize]
     }
 
+    // bilinear interpolation between the four cells around a point,
+    // smoother than `force_at` for tracing lines through the field
+    pub fn sample(&self, point: [f32; 2]) -> Vec2 {
+        if self.field.is_empty() {
+            return Vec2::ZERO;
+        }
+        let x = ((point[0] - self._left_x) / self.resolution).clamp(0.0, (self.cols - 1) as f32);
+        let y = ((point[1] - self._top_y) / self.resolution).clamp(0.0, (self.rows - 1) as f32);
+
+        let (i0, j0) = (x.floor() as usize, y.floor() as usize);
+        let (i1, j1) = ((i0 + 1).min(self.cols - 1), (j0 + 1).min(self.rows - 1));
+        let (tx, ty) = (x - i0 as f32, y - j0 as f32);
+
+        let top = self.cell(i0, j0).lerp(self.cell(i1, j0), tx);
+        let bottom = self.cell(i0, j1).lerp(self.cell(i1, j1), tx);
+        top.lerp(bottom, ty)
+    }
+
+    // world position of the cell at (i, j)
+    pub fn point_at(&self, i: usize, j: usize) -> Vec2 {
+        pt2(
+            i as f32 * self.resolution + self._left_x,
+            j as f32 * self.resolution + self._top_y,
+        )
+    }
+
+    // whether a world position lies inside the (extended) field
+    pub fn contains(&self, point: [f32; 2]) -> bool {
+        point[0] >= self._left_x
+            && point[0] <= self._right_x
+            && point[1] >= self._top_y
+            && point[1] <= self._bottom_y
+    }
+
+    pub fn cell(&self, i: usize, j: usize) -> Vec2 {
+        self.field[j * self.cols + i]
+    }
+
+    // all cells, row by row
+    pub fn cells(&self) -> &[Vec2] {
+        &self.field
+    }
+
+    pub fn cells_mut(&mut self) -> &mut [Vec2] {
+        &mut self.field
+    }
+
+    pub fn set_cell(&mut self, i: usize, j: usize, force: Vec2) {
+        self.field[j * self.cols + i] = force;
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn resolution(&self) -> f32 {
+        self.resolution
+    }
+
+    pub fn size(&self) -> (f32, f32) {
+        (self.width, self.height)
+    }
+
+    pub fn extend(&self) -> f32 {
+        self.extend
+    }
+
     pub fn display(&self, draw: &Draw, complete: bool, color: Option<Rgba>) {
         let color: Rgba = color.unwrap_or(Rgba::new(0.0, 0.0, 0.0, 0.2));
         let indices = self.displayable_bounds(complete);
 
         for i in indices[0][0]..indices[1][0] {
             for j in indices[0][1]..indices[1][1] {
-                let mut start = pt2(
-                    i as f32 * self.resolution + self._left_x,
-                    j as f32 * self.resolution + self._top_y,
-                );
-                let mut end = start + self.field[i][j] * self.resolution;
+                let mut start = self.point_at(i, j);
+                let mut end = start + self.cell(i, j) * self.resolution;
 
                 if complete {
                     start = self.map_to_visible(start);
@@ -149,7 +280,8 @@ impl FlowField {
         }
     }
 
-    fn map_to_visible(&self, point: Vec2) -> Vec2 {
+    // squeeze the extended field into the visible area, used when displaying it `complete`
+    pub(crate) fn map_to_visible(&self, point: Vec2) -> Vec2 {
         Vec2::new(
             map_range(point[0], self._left_x, self._right_x, 0.0, self.width),
             map_range(point[1], self._top_y, self._bottom_y, 0.0, self.height),
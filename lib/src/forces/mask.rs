@@ -0,0 +1,290 @@
+use crate::forces::field::ForceField;
+use crate::forces::image::{empty_image, Raster};
+use crate::forces::map::FlowField;
+use crate::forces::scalar::ScalarSource;
+use crate::utils::blur;
+use nannou::image::{self, DynamicImage, ImageResult};
+use nannou::prelude::Vec2;
+use std::path::Path;
+
+// Masks say how much of a field applies at (i, j): 1 inside a shape, 0 outside,
+// and a smooth ramp `feather` cells wide centered on the edge. They are `ScalarSource`s,
+// so they also work as plain scalar grids.
+//
+// Shapes and feathering are in field indices, like the fields they mask.
+//
+//     let circle = CircleMask::new([cx, cy], r, 20.0);
+//     field
+//         .merge_masked(&perlin, &circle)
+//         .merge_masked(&gradient, &Invert(circle));
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CircleMask {
+    pub center: [f64; 2],
+    pub radius: f64,
+    pub feather: f64,
+}
+
+impl CircleMask {
+    pub fn new(center: [f64; 2], radius: f64, feather: f64) -> Self {
+        CircleMask {
+            center,
+            radius,
+            feather,
+        }
+    }
+}
+
+impl ScalarSource for CircleMask {
+    fn get(&self, i: f64, j: f64) -> f32 {
+        let distance = (i - self.center[0]).hypot(j - self.center[1]);
+        coverage(distance - self.radius, self.feather)
+    }
+}
+
+// axis aligned rectangle between the corners `min` and `max`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RectMask {
+    pub min: [f64; 2],
+    pub max: [f64; 2],
+    pub feather: f64,
+}
+
+impl RectMask {
+    pub fn new(min: [f64; 2], max: [f64; 2], feather: f64) -> Self {
+        RectMask { min, max, feather }
+    }
+
+    // the rectangle covered by field indices [from, to), e.g. `FlowField::visible_bounds()`
+    pub fn bounds(bounds: [[usize; 2]; 2], feather: f64) -> Self {
+        let [from, to] = bounds;
+        Self::new(
+            [from[0] as f64, from[1] as f64],
+            [to[0] as f64, to[1] as f64],
+            feather,
+        )
+    }
+}
+
+impl ScalarSource for RectMask {
+    fn get(&self, i: f64, j: f64) -> f32 {
+        let center = [
+            (self.min[0] + self.max[0]) / 2.0,
+            (self.min[1] + self.max[1]) / 2.0,
+        ];
+        let half = [
+            (self.max[0] - self.min[0]).abs() / 2.0,
+            (self.max[1] - self.min[1]).abs() / 2.0,
+        ];
+        let dx = (i - center[0]).abs() - half[0];
+        let dy = (j - center[1]).abs() - half[1];
+
+        let outside = dx.max(0.0).hypot(dy.max(0.0));
+        let inside = dx.max(dy).min(0.0);
+        coverage(outside + inside, self.feather)
+    }
+}
+
+// closed polygon, the last point connects back to the first; any winding,
+// self-intersections follow the even-odd rule
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolygonMask {
+    pub points: Vec<[f64; 2]>,
+    pub feather: f64,
+}
+
+impl PolygonMask {
+    pub fn new(points: Vec<[f64; 2]>, feather: f64) -> Self {
+        PolygonMask { points, feather }
+    }
+}
+
+impl ScalarSource for PolygonMask {
+    fn get(&self, i: f64, j: f64) -> f32 {
+        let n = self.points.len();
+        if n < 3 {
+            return 0.0;
+        }
+
+        let mut distance = f64::MAX;
+        let mut inside = false;
+        for k in 0..n {
+            let [ax, ay] = self.points[k];
+            let [bx, by] = self.points[(k + n - 1) % n];
+
+            let (ex, ey) = (bx - ax, by - ay);
+            let (px, py) = (i - ax, j - ay);
+            let t = ((px * ex + py * ey) / (ex * ex + ey * ey).max(f64::EPSILON)).clamp(0.0, 1.0);
+            distance = distance.min((px - ex * t).hypot(py - ey * t));
+
+            if (ay > j) != (by > j) && i < ax + (j - ay) / (by - ay) * (bx - ax) {
+                inside = !inside;
+            }
+        }
+
+        coverage(if inside { -distance } else { distance }, self.feather)
+    }
+}
+
+// Signed distance function in field indices: negative inside, positive outside.
+pub type Sdf = Box<dyn Fn(f64, f64) -> f64 + Send + Sync>;
+
+// any shape given by its signed distance function, e.g. unions (`min`) of other shapes
+pub struct SdfMask {
+    pub feather: f64,
+    sdf: Sdf,
+}
+
+impl SdfMask {
+    pub fn new(sdf: impl Fn(f64, f64) -> f64 + Send + Sync + 'static, feather: f64) -> Self {
+        SdfMask {
+            feather,
+            sdf: Box::new(sdf),
+        }
+    }
+}
+
+impl ScalarSource for SdfMask {
+    fn get(&self, i: f64, j: f64) -> f32 {
+        coverage((self.sdf)(i, j), self.feather)
+    }
+}
+
+// The alpha channel of an image, placed like an `ImageField`: one pixel per cell from
+// (0, 0) unless `fit` stretches it over a range of field indices. Zero outside the image.
+pub struct ImageMask {
+    width: usize,
+    height: usize,
+    alpha: Vec<f32>,
+    feathered: Vec<f32>,
+    raster: Raster,
+}
+
+impl ImageMask {
+    // fails for an image without pixels
+    pub fn new(image: &DynamicImage) -> ImageResult<Self> {
+        let rgba = image.to_rgba8();
+        let (width, height) = (rgba.width() as usize, rgba.height() as usize);
+        if width == 0 || height == 0 {
+            return Err(empty_image());
+        }
+        let alpha: Vec<f32> = rgba.pixels().map(|p| p[3] as f32 / 255.0).collect();
+
+        Ok(ImageMask {
+            width,
+            height,
+            feathered: alpha.clone(),
+            alpha,
+            raster: Raster::new(width, height),
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> ImageResult<Self> {
+        let image = image::open(path)?;
+        Self::new(&image)
+    }
+
+    // gaussian blur radius (in pixels) softening the alpha edges
+    pub fn feather(&mut self, radius: f32) -> &mut Self {
+        self.feathered = blur(&self.alpha, self.width, self.height, radius.max(0.0));
+        self
+    }
+
+    // stretch the image over the field indices [from, to)
+    pub fn fit(&mut self, bounds: [[usize; 2]; 2]) -> &mut Self {
+        self.raster.fit(bounds);
+        self
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+}
+
+impl ScalarSource for ImageMask {
+    fn get(&self, i: f64, j: f64) -> f32 {
+        self.raster.sample(&self.feathered, i, j).unwrap_or(0.0)
+    }
+}
+
+// 1 where the mask is 0 and the other way around
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Invert<M>(pub M);
+
+impl<M: ScalarSource> ScalarSource for Invert<M> {
+    fn get(&self, i: f64, j: f64) -> f32 {
+        1.0 - self.0.get(i, j)
+    }
+
+    fn get_at(&self, i: f64, j: f64, t: f64) -> f32 {
+        1.0 - self.0.get_at(i, j, t)
+    }
+}
+
+// A force weighted by a mask, itself a `ForceField` so it can be merged or masked again.
+pub struct Masked<F, M> {
+    pub force: F,
+    pub mask: M,
+}
+
+impl<F: ForceField, M: ScalarSource> Masked<F, M> {
+    pub fn new(force: F, mask: M) -> Self {
+        Masked { force, mask }
+    }
+}
+
+impl<F: ForceField, M: ScalarSource> ForceField for Masked<F, M> {
+    fn get(&self, i: f64, j: f64) -> Vec2 {
+        match self.mask.get(i, j) {
+            w if w <= 0.0 => Vec2::ZERO,
+            w => self.force.get(i, j) * w,
+        }
+    }
+
+    fn get_at(&self, i: f64, j: f64, t: f64) -> Vec2 {
+        match self.mask.get_at(i, j, t) {
+            w if w <= 0.0 => Vec2::ZERO,
+            w => self.force.get_at(i, j, t) * w,
+        }
+    }
+}
+
+impl FlowField {
+    // like `merge`, weighted by the mask; cells outside the mask never sample the force
+    pub fn merge_masked(&mut self, force: &impl ForceField, mask: &impl ScalarSource) -> &mut Self {
+        self.merge_weighted(|i, j| match mask.get(i, j) {
+            w if w <= 0.0 => Vec2::ZERO,
+            w => force.get(i, j) * w,
+        })
+    }
+
+    pub fn merge_masked_at(
+        &mut self,
+        force: &impl ForceField,
+        mask: &impl ScalarSource,
+        t: f64,
+    ) -> &mut Self {
+        self.merge_weighted(|i, j| match mask.get_at(i, j, t) {
+            w if w <= 0.0 => Vec2::ZERO,
+            w => force.get_at(i, j, t) * w,
+        })
+    }
+
+    fn merge_weighted(&mut self, f: impl Fn(f64, f64) -> Vec2) -> &mut Self {
+        let cols = self.cols();
+        for (n, v) in self.cells_mut().iter_mut().enumerate() {
+            *v += f((n % cols) as f64, (n / cols) as f64);
+        }
+        self
+    }
+}
+
+// 1 inside, 0 outside, smoothstep across a band `feather` wide centered on the edge
+fn coverage(distance: f64, feather: f64) -> f32 {
+    if feather <= 0.0 {
+        return if distance <= 0.0 { 1.0 } else { 0.0 };
+    }
+
+    let t = (0.5 - distance / feather).clamp(0.0, 1.0);
+    (t * t * (3.0 - 2.0 * t)) as f32
+}
@@ -1,2 +1,16 @@
+pub mod analysis;
+pub mod evenly;
+pub mod expr;
 pub mod field;
+pub mod image;
 pub mod map;
+pub mod mask;
+pub mod ode;
+pub mod path;
+pub mod post;
+pub mod quadtree;
+pub mod render;
+pub mod scalar;
+pub mod storage;
+pub mod streamline;
+pub mod volume;
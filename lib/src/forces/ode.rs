@@ -0,0 +1,300 @@
+use crate::forces::field::ForceField;
+use nannou::prelude::Vec2;
+
+// Classic dynamical systems as force fields.
+//
+// The field maps a rectangle of field indices (the viewport, e.g. `FlowField::visible_bounds()`)
+// onto a window of the system's state space, with y pointing up like in the usual phase portraits.
+// 3D systems are sliced by a plane at a fixed depth and their velocity projected onto it.
+//
+//     let mut lorenz = OdeField::new(Ode::lorenz(), 1.0, field.visible_bounds());
+//     lorenz.plane(Plane::XZ, 0.0);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ode {
+    // 2D systems
+    VanDerPol {
+        mu: f64,
+    },
+    Duffing {
+        alpha: f64,
+        beta: f64,
+        delta: f64,
+        gamma: f64,
+        omega: f64,
+    },
+    LotkaVolterra {
+        alpha: f64,
+        beta: f64,
+        delta: f64,
+        gamma: f64,
+    },
+    Pendulum {
+        gravity: f64,
+        length: f64,
+        damping: f64,
+    },
+
+    // 3D systems
+    Lorenz {
+        sigma: f64,
+        rho: f64,
+        beta: f64,
+    },
+    Rossler {
+        a: f64,
+        b: f64,
+        c: f64,
+    },
+    Thomas {
+        b: f64,
+    },
+}
+
+// plane a 3D system is sliced by; the remaining axis is the depth
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Plane {
+    XY,
+    XZ,
+    YZ,
+}
+
+impl Ode {
+    pub fn van_der_pol() -> Self {
+        Ode::VanDerPol { mu: 1.0 }
+    }
+
+    pub fn duffing() -> Self {
+        Ode::Duffing {
+            alpha: -1.0,
+            beta: 1.0,
+            delta: 0.3,
+            gamma: 0.5,
+            omega: 1.2,
+        }
+    }
+
+    pub fn lotka_volterra() -> Self {
+        Ode::LotkaVolterra {
+            alpha: 2.0 / 3.0,
+            beta: 4.0 / 3.0,
+            delta: 1.0,
+            gamma: 1.0,
+        }
+    }
+
+    pub fn pendulum() -> Self {
+        Ode::Pendulum {
+            gravity: 9.81,
+            length: 1.0,
+            damping: 0.2,
+        }
+    }
+
+    pub fn lorenz() -> Self {
+        Ode::Lorenz {
+            sigma: 10.0,
+            rho: 28.0,
+            beta: 8.0 / 3.0,
+        }
+    }
+
+    pub fn rossler() -> Self {
+        Ode::Rossler {
+            a: 0.2,
+            b: 0.2,
+            c: 5.7,
+        }
+    }
+
+    pub fn thomas() -> Self {
+        Ode::Thomas { b: 0.208186 }
+    }
+
+    pub fn is_3d(&self) -> bool {
+        matches!(
+            self,
+            Ode::Lorenz { .. } | Ode::Rossler { .. } | Ode::Thomas { .. }
+        )
+    }
+
+    // derivative of a 2D system at (x, y), time t drives forcing terms (Duffing)
+    pub fn derivative2(&self, x: f64, y: f64, t: f64) -> [f64; 2] {
+        match *self {
+            Ode::VanDerPol { mu } => [y, mu * (1.0 - x * x) * y - x],
+            Ode::Duffing {
+                alpha,
+                beta,
+                delta,
+                gamma,
+                omega,
+            } => [
+                y,
+                -delta * y - alpha * x - beta * x * x * x + gamma * (omega * t).cos(),
+            ],
+            Ode::LotkaVolterra {
+                alpha,
+                beta,
+                delta,
+                gamma,
+            } => [alpha * x - beta * x * y, delta * x * y - gamma * y],
+            Ode::Pendulum {
+                gravity,
+                length,
+                damping,
+            } => [y, -damping * y - gravity / length * x.sin()],
+            _ => {
+                let [dx, dy, _] = self.derivative3(x, y, 0.0);
+                [dx, dy]
+            }
+        }
+    }
+
+    // derivative of a 3D system at (x, y, z); 2D systems treat z as constant
+    pub fn derivative3(&self, x: f64, y: f64, z: f64) -> [f64; 3] {
+        match *self {
+            Ode::Lorenz { sigma, rho, beta } => {
+                [sigma * (y - x), x * (rho - z) - y, x * y - beta * z]
+            }
+            Ode::Rossler { a, b, c } => [-y - z, x + a * y, b + z * (x - c)],
+            Ode::Thomas { b } => [y.sin() - b * x, z.sin() - b * y, x.sin() - b * z],
+            _ => {
+                let [dx, dy] = self.derivative2(x, y, 0.0);
+                [dx, dy, 0.0]
+            }
+        }
+    }
+
+    // a window of state space that shows the interesting part of the system
+    pub fn default_window(&self, plane: Plane) -> [[f64; 2]; 2] {
+        match (self, plane) {
+            (Ode::VanDerPol { .. }, _) => [[-4.0, -4.0], [4.0, 4.0]],
+            (Ode::Duffing { .. }, _) => [[-2.0, -2.0], [2.0, 2.0]],
+            (Ode::LotkaVolterra { .. }, _) => [[0.0, 0.0], [3.0, 3.0]],
+            (Ode::Pendulum { .. }, _) => [
+                [-2.0 * std::f64::consts::PI, -8.0],
+                [2.0 * std::f64::consts::PI, 8.0],
+            ],
+            (Ode::Lorenz { .. }, Plane::XY) => [[-25.0, -30.0], [25.0, 30.0]],
+            (Ode::Lorenz { .. }, Plane::XZ) => [[-25.0, 0.0], [25.0, 50.0]],
+            (Ode::Lorenz { .. }, Plane::YZ) => [[-30.0, 0.0], [30.0, 50.0]],
+            (Ode::Rossler { .. }, Plane::XY) => [[-12.0, -12.0], [12.0, 12.0]],
+            (Ode::Rossler { .. }, _) => [[-12.0, 0.0], [12.0, 24.0]],
+            (Ode::Thomas { .. }, _) => [[-5.0, -5.0], [5.0, 5.0]],
+        }
+    }
+}
+
+pub struct OdeField {
+    pub strength: f64,
+    pub normalize: bool,
+    ode: Ode,
+    plane: Plane,
+    depth: f64,
+
+    // field indices [from, to) mapped onto the state space window [min, max]
+    bounds: [[f64; 2]; 2],
+    window: [[f64; 2]; 2],
+}
+
+impl OdeField {
+    pub fn new(ode: Ode, strength: f64, bounds: [[usize; 2]; 2]) -> Self {
+        let [from, to] = bounds;
+        OdeField {
+            strength,
+            normalize: true,
+            ode,
+            plane: Plane::XY,
+            depth: 0.0,
+            bounds: [
+                [from[0] as f64, from[1] as f64],
+                [to[0] as f64, to[1] as f64],
+            ],
+            window: ode.default_window(Plane::XY),
+        }
+    }
+
+    // slice a 3D system by `plane` at `depth` along the remaining axis
+    // this also resets the window to the system's default for that plane
+    pub fn plane(&mut self, plane: Plane, depth: f64) -> &mut Self {
+        self.plane = plane;
+        self.depth = depth;
+        self.window = self.ode.default_window(plane);
+        self
+    }
+
+    // the rectangle [min, max] of state space shown in the viewport
+    pub fn window(&mut self, min: [f64; 2], max: [f64; 2]) -> &mut Self {
+        self.window = [min, max];
+        self
+    }
+
+    // true  : unit vectors (times strength), shows the direction field
+    // false : raw velocities (times strength), fast regions dominate
+    pub fn normalize(&mut self, normalize: bool) -> &mut Self {
+        self.normalize = normalize;
+        self
+    }
+
+    // state space coordinates of field indices (i, j)
+    pub fn to_state(&self, i: f64, j: f64) -> [f64; 2] {
+        let [[i0, j0], [i1, j1]] = self.bounds;
+        let [[x0, y0], [x1, y1]] = self.window;
+        let u = (i - i0) / (i1 - i0);
+        let v = (j - j0) / (j1 - j0);
+        [x0 + u * (x1 - x0), y1 - v * (y1 - y0)]
+    }
+
+    fn velocity(&self, u: f64, v: f64, t: f64) -> [f64; 2] {
+        if !self.ode.is_3d() {
+            return self.ode.derivative2(u, v, t);
+        }
+
+        let d = self.depth;
+        match self.plane {
+            Plane::XY => {
+                let [dx, dy, _] = self.ode.derivative3(u, v, d);
+                [dx, dy]
+            }
+            Plane::XZ => {
+                let [dx, _, dz] = self.ode.derivative3(u, d, v);
+                [dx, dz]
+            }
+            Plane::YZ => {
+                let [_, dy, dz] = self.ode.derivative3(d, u, v);
+                [dy, dz]
+            }
+        }
+    }
+}
+
+impl ForceField for OdeField {
+    fn get(&self, i: f64, j: f64) -> Vec2 {
+        self.get_at(i, j, 0.0)
+    }
+
+    fn get_at(&self, i: f64, j: f64, t: f64) -> Vec2 {
+        let [u, v] = self.to_state(i, j);
+        let [du, dv] = self.velocity(u, v, t);
+
+        // state space y points up, field j points down
+        let [[i0, j0], [i1, j1]] = self.bounds;
+        let [[x0, y0], [x1, y1]] = self.window;
+        let velocity = Vec2::new(
+            (du * (i1 - i0) / (x1 - x0)) as f32,
+            (-dv * (j1 - j0) / (y1 - y0)) as f32,
+        );
+
+        let velocity = if self.normalize {
+            velocity.normalize_or_zero()
+        } else {
+            velocity
+        };
+
+        if velocity.is_finite() {
+            velocity * self.strength as f32
+        } else {
+            Vec2::ZERO
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::forces::field::ForceField;
+use crate::svg::{self, Polyline, SvgError};
+use nannou::prelude::Vec2;
+use std::path::Path;
+
+// Force field that flows along a set of polylines.
+//
+// Points are in field indices, like the position of an `AttractorField`.
+// Near a curve the force follows the tangent of the closest curve point;
+// further away it bends towards the curve, and beyond `width` it is zero.
+//
+// strength   : magnitude of the force right on the curve
+// width      : influence width (in cells) on either side of the curve
+// attraction : 0.0 only follows the curves, 1.0 pulls fully towards them at the edge
+pub struct PathField {
+    pub strength: f64,
+    pub width: f64,
+    pub attraction: f64,
+    polylines: Vec<Polyline>,
+}
+
+impl PathField {
+    pub fn new(polylines: Vec<Polyline>, strength: f64, width: f64) -> Self {
+        PathField {
+            strength,
+            width: width.max(f64::EPSILON),
+            attraction: 0.5,
+            polylines: polylines.into_iter().filter(|p| p.len() > 1).collect(),
+        }
+    }
+
+    // load every <path> of an svg file, in svg user units; see `fit`
+    pub fn from_svg(path: impl AsRef<Path>, strength: f64, width: f64) -> Result<Self, SvgError> {
+        Ok(Self::new(svg::load_paths(path)?, strength, width))
+    }
+
+    pub fn attraction(&mut self, attraction: f64) -> &mut Self {
+        self.attraction = attraction.clamp(0.0, 1.0);
+        self
+    }
+
+    // scale and center the curves (keeping their aspect ratio) into the field indices [from, to),
+    // e.g. `FlowField::visible_bounds()` to fill the window
+    pub fn fit(&mut self, bounds: [[usize; 2]; 2]) -> &mut Self {
+        let points = self.polylines.iter().flatten();
+        let min = points.clone().fold(Vec2::splat(f32::MAX), |a, p| a.min(*p));
+        let max = points.fold(Vec2::splat(f32::MIN), |a, p| a.max(*p));
+        let size = (max - min).max(Vec2::splat(f32::EPSILON));
+
+        let [from, to] = bounds;
+        let from = Vec2::new(from[0] as f32, from[1] as f32);
+        let target = Vec2::new(to[0] as f32, to[1] as f32) - from;
+        let scale = (target.x / size.x).min(target.y / size.y);
+        let offset = from + (target - size * scale) / 2.0;
+
+        for point in self.polylines.iter_mut().flatten() {
+            *point = (*point - min) * scale + offset;
+        }
+        self
+    }
+
+    pub fn polylines(&self) -> &[Polyline] {
+        &self.polylines
+    }
+
+    // closest point on any curve, with the tangent of its segment
+    fn closest(&self, p: Vec2) -> Option<(Vec2, Vec2)> {
+        let mut best: Option<(f32, Vec2, Vec2)> = None;
+
+        for polyline in &self.polylines {
+            for segment in polyline.windows(2) {
+                let (a, b) = (segment[0], segment[1]);
+                let ab = b - a;
+                let len2 = ab.length_squared();
+                if len2 == 0.0 {
+                    continue;
+                }
+
+                let t = ((p - a).dot(ab) / len2).clamp(0.0, 1.0);
+                let q = a + ab * t;
+                let d2 = p.distance_squared(q);
+                if best.is_none_or(|(bd, _, _)| d2 < bd) {
+                    best = Some((d2, q, ab / len2.sqrt()));
+                }
+            }
+        }
+
+        best.map(|(_, q, tangent)| (q, tangent))
+    }
+}
+
+impl ForceField for PathField {
+    fn get(&self, i: f64, j: f64) -> Vec2 {
+        let p = Vec2::new(i as f32, j as f32);
+        let (q, tangent) = match self.closest(p) {
+            Some(closest) => closest,
+            None => return Vec2::ZERO,
+        };
+
+        let distance = p.distance(q) / self.width.max(f64::EPSILON) as f32;
+        if distance >= 1.0 {
+            return Vec2::ZERO;
+        }
+
+        // attraction grows with distance, the overall force fades out towards the edge
+        let pull = (q - p).normalize_or_zero();
+        let blend = distance * self.attraction as f32;
+        let falloff = 1.0 - distance * distance;
+        tangent.lerp(pull, blend) * falloff * self.strength as f32
+    }
+}
@@ -0,0 +1,93 @@
+use crate::common::Seedable;
+use crate::forces::map::FlowField;
+use crate::utils::{blur, box_blur, hash2_unit};
+use nannou::glam::Vec2;
+use nannou::prelude::TAU;
+
+// Passes over a baked field, chained after `merge` the same way as `zero()`:
+//
+//     field.zero().merge(&perlin).smooth(Kernel::Gaussian(2.0)).quantize(8, 0.0).normalize();
+//
+// They work on the cells as they are, so order matters: quantizing then smoothing
+// gives soft turns between the directions, smoothing then quantizing keeps them crisp.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kernel {
+    // plain average of the cells up to `radius` away on each axis
+    Box(usize),
+    // gaussian falloff, sigma is half the radius
+    Gaussian(f32),
+}
+
+impl FlowField {
+    // unit length everywhere, zero vectors stay zero
+    pub fn normalize(&mut self) -> &mut Self {
+        self.map_cells(|v| v.normalize_or_zero())
+    }
+
+    // keep magnitudes within [min, max] without changing directions
+    pub fn clamp_magnitude(&mut self, min: f32, max: f32) -> &mut Self {
+        let (min, max) = (min.min(max), min.max(max));
+        self.map_cells(|v| {
+            let length = v.length();
+            if length <= f32::EPSILON {
+                return v;
+            }
+            v * (length.clamp(min, max) / length)
+        })
+    }
+
+    // snap every direction to the nearest of `directions` evenly spaced angles,
+    // starting from `offset` radians (0 is +x), magnitudes are kept
+    pub fn quantize(&mut self, directions: usize, offset: f32) -> &mut Self {
+        let step = TAU / directions.max(1) as f32;
+        self.map_cells(|v| {
+            let length = v.length();
+            if length <= f32::EPSILON {
+                return v;
+            }
+            let angle = ((v.y.atan2(v.x) - offset) / step).round() * step + offset;
+            Vec2::new(angle.cos(), angle.sin()) * length
+        })
+    }
+
+    // average every cell with its neighbours, x and y components separately
+    pub fn smooth(&mut self, kernel: Kernel) -> &mut Self {
+        let (cols, rows) = (self.cols(), self.rows());
+        let pass = |values: &[f32]| match kernel {
+            Kernel::Box(radius) => box_blur(values, cols, rows, radius),
+            Kernel::Gaussian(radius) => blur(values, cols, rows, radius),
+        };
+
+        let xs = pass(&self.cells().iter().map(|v| v.x).collect::<Vec<_>>());
+        let ys = pass(&self.cells().iter().map(|v| v.y).collect::<Vec<_>>());
+        for (n, v) in self.cells_mut().iter_mut().enumerate() {
+            *v = Vec2::new(xs[n], ys[n]);
+        }
+
+        self
+    }
+
+    // rotate every cell by up to `angle` radians either way and scale it by up to
+    // `magnitude` (as a fraction) either way, from noise seeded with the field's seed
+    pub fn jitter(&mut self, angle: f32, magnitude: f32) -> &mut Self {
+        let (cols, seed) = (self.cols(), self.seed());
+        for (n, v) in self.cells_mut().iter_mut().enumerate() {
+            let (i, j) = ((n % cols) as i64, (n / cols) as i64);
+            let turn = (hash2_unit(i, j, seed) * 2.0 - 1.0) * angle;
+            let scale = 1.0 + (hash2_unit(i, j, seed ^ 0x5bd1_e995) * 2.0 - 1.0) * magnitude;
+
+            let (sin, cos) = turn.sin_cos();
+            *v = Vec2::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos) * scale;
+        }
+
+        self
+    }
+
+    fn map_cells(&mut self, f: impl Fn(Vec2) -> Vec2) -> &mut Self {
+        for v in self.cells_mut() {
+            *v = f(*v);
+        }
+        self
+    }
+}
@@ -0,0 +1,469 @@
+use crate::forces::field::ForceField;
+use nannou::color::Rgba;
+use nannou::glam::Vec2;
+use nannou::math::map_range;
+use nannou::Draw;
+use std::mem::size_of;
+
+// A flow field stored in a quadtree instead of a uniform grid: big cells where the field
+// is smooth, small ones where it turns quickly (around attractors, paths, critical points).
+//
+// It uses the same field indices as a `FlowField` with the same size, resolution and extend,
+// so any `ForceField` can be merged into either. `resolution` is the size of the smallest cell.
+// Every leaf keeps the vectors at its four corners and is sampled bilinearly between them.
+//
+// A leaf is split while the bilinear guess at its center and edge midpoints is further than
+// `tolerance` from the real field, or while it is larger than `coarsest` cells.
+//
+//     let mut quad = QuadField::new(w, h, 2.0, 0.5);
+//     quad.tolerance(0.02).merge(&attractor).merge(&perlin);
+//     println!("{:?}", quad.stats());
+
+pub struct QuadField {
+    cols: usize,
+    rows: usize,
+    resolution: f32,
+    extend: f32,
+    tolerance: f32,
+    coarsest: f32,
+    nodes: Vec<Node>,
+
+    width: f32,
+    height: f32,
+    _left_x: f32,
+    _right_x: f32,
+    _top_y: f32,
+    _bottom_y: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    // top left corner and side, in field indices
+    origin: Vec2,
+    size: f32,
+    // vectors at (0, 0), (1, 0), (0, 1), (1, 1)
+    corners: [Vec2; 4],
+    // index of the first of four consecutive children
+    children: Option<usize>,
+    // largest difference seen between the field and the leaf's bilinear guess
+    error: f32,
+}
+
+// a leaf of the tree, in world coordinates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuadCell {
+    pub position: Vec2,
+    pub size: f32,
+    pub depth: usize,
+    // vector at the center of the cell
+    pub force: Vec2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuadStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub depth: usize,
+    // bytes used by the tree, and by a `FlowField` grid of the same resolution
+    pub bytes: usize,
+    pub uniform_bytes: usize,
+    // estimated interpolation error over the leaves, in vector units
+    pub max_error: f32,
+    pub mean_error: f32,
+}
+
+impl QuadField {
+    pub const DEFAULT_TOLERANCE: f32 = 0.05;
+    pub const DEFAULT_COARSEST: usize = 16;
+
+    pub fn new(w: f32, h: f32, resolution: f32, extend: f32) -> Self {
+        let cols = f32::ceil(w * (1.0 + 2.0 * extend) / resolution) as usize;
+        let rows = f32::ceil(h * (1.0 + 2.0 * extend) / resolution) as usize;
+
+        let mut field = QuadField {
+            cols,
+            rows,
+            resolution,
+            extend,
+            tolerance: Self::DEFAULT_TOLERANCE,
+            coarsest: Self::DEFAULT_COARSEST as f32,
+            nodes: Vec::new(),
+            width: w,
+            height: h,
+            _left_x: -w * extend,
+            _right_x: w * (1.0 + extend),
+            _top_y: -h * extend,
+            _bottom_y: h * (1.0 + extend),
+        };
+        field.zero();
+        field
+    }
+
+    // drop every cell and start over from a single zero root
+    pub fn zero(&mut self) -> &mut Self {
+        let size = self.cols.max(self.rows).max(1).next_power_of_two() as f32;
+        self.nodes.clear();
+        self.nodes.push(Node {
+            origin: Vec2::ZERO,
+            size,
+            corners: [Vec2::ZERO; 4],
+            children: None,
+            error: 0.0,
+        });
+        self
+    }
+
+    // largest difference (in vector units) allowed between the field and a leaf's interpolation
+    pub fn tolerance(&mut self, tolerance: f32) -> &mut Self {
+        self.tolerance = tolerance.max(0.0);
+        self
+    }
+
+    // largest leaf side in cells, so that small features inside big smooth cells are not missed
+    pub fn coarsest(&mut self, cells: usize) -> &mut Self {
+        self.coarsest = cells.max(1) as f32;
+        self
+    }
+
+    // add a force to the field, refining the tree where the sum needs it
+    pub fn merge(&mut self, force: &impl ForceField) -> &mut Self {
+        self.refine_with(|i, j| force.get(i as f64, j as f64))
+    }
+
+    // same as `merge`, sampling the force at time t
+    pub fn merge_at(&mut self, force: &impl ForceField, t: f64) -> &mut Self {
+        self.refine_with(|i, j| force.get_at(i as f64, j as f64, t))
+    }
+
+    // value at the leaf corner nearest to a world position
+    pub fn force_at(&self, point: [f32; 2]) -> Vec2 {
+        let p = self.to_index(point);
+        let node = &self.nodes[self.leaf_at(p)];
+        let local = (p - node.origin) / node.size;
+        node.corners[(local.x >= 0.5) as usize + 2 * (local.y >= 0.5) as usize]
+    }
+
+    // bilinear interpolation inside the leaf holding a world position
+    pub fn sample(&self, point: [f32; 2]) -> Vec2 {
+        self.sample_index(self.to_index(point))
+    }
+
+    pub fn index_at(&self, point: [f32; 2]) -> [usize; 2] {
+        let p = self.to_index(point);
+        [p.x as usize, p.y as usize]
+    }
+
+    // world position of the cell at (i, j) of the equivalent uniform grid
+    pub fn point_at(&self, i: usize, j: usize) -> Vec2 {
+        self.to_world(Vec2::new(i as f32, j as f32))
+    }
+
+    // whether a world position lies inside the (extended) field
+    pub fn contains(&self, point: [f32; 2]) -> bool {
+        point[0] >= self._left_x
+            && point[0] <= self._right_x
+            && point[1] >= self._top_y
+            && point[1] <= self._bottom_y
+    }
+
+    pub fn leaves(&self) -> Vec<QuadCell> {
+        let mut leaves = Vec::new();
+        let mut stack = vec![(0, 0)];
+        while let Some((n, depth)) = stack.pop() {
+            let node = &self.nodes[n];
+            match node.children {
+                Some(first) => stack.extend((first..first + 4).map(|c| (c, depth + 1))),
+                None if self.overlaps(node) => leaves.push(QuadCell {
+                    position: self.to_world(node.origin),
+                    size: node.size * self.resolution,
+                    depth,
+                    force: bilinear(node, node.origin + Vec2::splat(node.size / 2.0)),
+                }),
+                None => {}
+            }
+        }
+        leaves
+    }
+
+    pub fn stats(&self) -> QuadStats {
+        let leaves = self.leaves();
+        let errors: Vec<f32> = self
+            .nodes
+            .iter()
+            .filter(|node| node.children.is_none() && self.overlaps(node))
+            .map(|node| node.error)
+            .collect();
+
+        QuadStats {
+            nodes: self.nodes.len(),
+            leaves: leaves.len(),
+            depth: leaves.iter().map(|leaf| leaf.depth).max().unwrap_or(0),
+            bytes: size_of::<Self>() + self.nodes.capacity() * size_of::<Node>(),
+            uniform_bytes: self.cols * self.rows * size_of::<Vec2>(),
+            max_error: errors.iter().copied().fold(0.0, f32::max),
+            mean_error: errors.iter().sum::<f32>() / errors.len().max(1) as f32,
+        }
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn resolution(&self) -> f32 {
+        self.resolution
+    }
+
+    pub fn size(&self) -> (f32, f32) {
+        (self.width, self.height)
+    }
+
+    pub fn extend(&self) -> f32 {
+        self.extend
+    }
+
+    // a line per leaf from its center, scaled by the leaf size
+    pub fn display(&self, draw: &Draw, complete: bool, color: Option<Rgba>) {
+        let color: Rgba = color.unwrap_or(Rgba::new(0.0, 0.0, 0.0, 0.2));
+        for leaf in self.displayable_leaves(complete) {
+            let mut start = leaf.position + Vec2::splat(leaf.size / 2.0);
+            let mut end = start + leaf.force * leaf.size / 2.0;
+
+            if complete {
+                start = self.map_to_visible(start);
+                end = self.map_to_visible(end);
+            }
+
+            draw.line().start(start).end(end).color(color);
+            draw.ellipse().xy(end).radius(1.0).color(color);
+        }
+    }
+
+    // outlines of the leaves, shows where the tree refined
+    pub fn display_cells(&self, draw: &Draw, complete: bool, color: Option<Rgba>) {
+        let color: Rgba = color.unwrap_or(Rgba::new(0.0, 0.0, 0.0, 0.15));
+        for leaf in self.displayable_leaves(complete) {
+            let mut min = leaf.position;
+            let mut max = leaf.position + Vec2::splat(leaf.size);
+            if complete {
+                min = self.map_to_visible(min);
+                max = self.map_to_visible(max);
+            }
+
+            draw.rect()
+                .xy((min + max) / 2.0)
+                .wh(max - min)
+                .no_fill()
+                .stroke_weight(1.0)
+                .stroke(color);
+        }
+    }
+
+    fn displayable_leaves(&self, complete: bool) -> impl Iterator<Item = QuadCell> + '_ {
+        let (w, h) = (self.width, self.height);
+        self.leaves().into_iter().filter(move |leaf| {
+            let center = leaf.position + Vec2::splat(leaf.size / 2.0);
+            complete || (0.0..=w).contains(&center.x) && (0.0..=h).contains(&center.y)
+        })
+    }
+
+    // refine every leaf against `previous interpolation + f`, so merges add up like on a grid
+    fn refine_with(&mut self, f: impl Fn(f32, f32) -> Vec2) -> &mut Self {
+        let leaves: Vec<usize> = (0..self.nodes.len())
+            .filter(|n| self.nodes[*n].children.is_none())
+            .collect();
+
+        for n in leaves {
+            let previous = self.nodes[n];
+            let eval = |p: Vec2| bilinear(&previous, p) + f(p.x, p.y);
+            let node = &mut self.nodes[n];
+            node.corners = corner_points(node).map(eval);
+            self.refine(n, &eval);
+        }
+
+        self.nodes.shrink_to_fit();
+        self
+    }
+
+    fn refine(&mut self, n: usize, eval: &dyn Fn(Vec2) -> Vec2) {
+        let node = self.nodes[n];
+        let Node { origin, size, .. } = node;
+        let half = size / 2.0;
+
+        let probes = [
+            origin + Vec2::new(half, half),
+            origin + Vec2::new(half, 0.0),
+            origin + Vec2::new(0.0, half),
+            origin + Vec2::new(size, half),
+            origin + Vec2::new(half, size),
+        ];
+        let values = probes.map(eval);
+        let error = probes
+            .iter()
+            .zip(values.iter())
+            .map(|(p, v)| bilinear(&node, *p).distance(*v))
+            .fold(0.0, f32::max);
+        self.nodes[n].error = error;
+
+        let split = error > self.tolerance || size > self.coarsest;
+        if !split || size <= 1.0 || !self.overlaps(&node) {
+            return;
+        }
+
+        // the 3x3 lattice of corner values shared by the four children
+        let [center, top, left, right, bottom] = values;
+        let [c00, c20, c02, c22] = node.corners;
+        let lattice = [[c00, top, c20], [left, center, right], [c02, bottom, c22]];
+
+        let first = self.nodes.len();
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            self.nodes.push(Node {
+                origin: origin + Vec2::new(dx as f32, dy as f32) * half,
+                size: half,
+                corners: [
+                    lattice[dy][dx],
+                    lattice[dy][dx + 1],
+                    lattice[dy + 1][dx],
+                    lattice[dy + 1][dx + 1],
+                ],
+                children: None,
+                error: 0.0,
+            });
+        }
+        self.nodes[n].children = Some(first);
+
+        for child in first..first + 4 {
+            self.refine(child, eval);
+        }
+    }
+
+    fn leaf_at(&self, p: Vec2) -> usize {
+        let mut n = 0;
+        while let Some(first) = self.nodes[n].children {
+            let node = &self.nodes[n];
+            let local = (p - node.origin) / node.size;
+            n = first + (local.x >= 0.5) as usize + 2 * (local.y >= 0.5) as usize;
+        }
+        n
+    }
+
+    fn sample_index(&self, p: Vec2) -> Vec2 {
+        bilinear(&self.nodes[self.leaf_at(p)], p)
+    }
+
+    // whether a node covers any part of the grid, the root is padded to a power of two
+    fn overlaps(&self, node: &Node) -> bool {
+        node.origin.x < self.cols as f32 && node.origin.y < self.rows as f32
+    }
+
+    fn to_index(&self, point: [f32; 2]) -> Vec2 {
+        Vec2::new(
+            ((point[0] - self._left_x) / self.resolution)
+                .clamp(0.0, self.cols.saturating_sub(1) as f32),
+            ((point[1] - self._top_y) / self.resolution)
+                .clamp(0.0, self.rows.saturating_sub(1) as f32),
+        )
+    }
+
+    fn to_world(&self, p: Vec2) -> Vec2 {
+        Vec2::new(
+            p.x * self.resolution + self._left_x,
+            p.y * self.resolution + self._top_y,
+        )
+    }
+
+    fn map_to_visible(&self, point: Vec2) -> Vec2 {
+        Vec2::new(
+            map_range(point[0], self._left_x, self._right_x, 0.0, self.width),
+            map_range(point[1], self._top_y, self._bottom_y, 0.0, self.height),
+        )
+    }
+}
+
+// sampled in field indices, so a quadtree can be baked into a `FlowField` or merged into another
+impl ForceField for QuadField {
+    fn get(&self, i: f64, j: f64) -> Vec2 {
+        let p = Vec2::new(i as f32, j as f32);
+        self.sample_index(p.clamp(Vec2::ZERO, Vec2::new(self.cols as f32, self.rows as f32)))
+    }
+}
+
+fn corner_points(node: &Node) -> [Vec2; 4] {
+    let Node { origin, size, .. } = *node;
+    [
+        origin,
+        origin + Vec2::new(size, 0.0),
+        origin + Vec2::new(0.0, size),
+        origin + Vec2::splat(size),
+    ]
+}
+
+fn bilinear(node: &Node, p: Vec2) -> Vec2 {
+    let t = ((p - node.origin) / node.size).clamp(Vec2::ZERO, Vec2::ONE);
+    let [c00, c10, c01, c11] = node.corners;
+    c00.lerp(c10, t.x).lerp(c01.lerp(c11, t.x), t.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forces::field::{AttractorField, GradientField};
+
+    #[test]
+    fn uniform_fields_stay_coarse_and_exact() {
+        let mut quad = QuadField::new(200.0, 120.0, 2.0, 0.0);
+        quad.merge(&GradientField::new(1.5, 0.3));
+        let expected = GradientField::new(1.5, 0.3).get(0.0, 0.0);
+
+        let stats = quad.stats();
+        assert!(stats.max_error < 1e-5, "{:?}", stats);
+        assert!(
+            stats.leaves < quad.cols() * quad.rows() / 100,
+            "{:?}",
+            stats
+        );
+        for leaf in quad.leaves() {
+            assert!(leaf.size <= QuadField::DEFAULT_COARSEST as f32 * 2.0);
+        }
+        for point in [[0.0, 0.0], [37.5, 91.0], [199.0, 119.0]] {
+            assert!(quad.sample(point).distance(expected) < 1e-5);
+        }
+    }
+
+    #[test]
+    fn attractors_refine_around_their_center() {
+        let attractor = AttractorField::new(1.0, 50, 30);
+        let leaves = |tolerance: f32| {
+            let mut quad = QuadField::new(200.0, 120.0, 2.0, 0.0);
+            quad.tolerance(tolerance).merge(&attractor);
+            quad.leaves()
+        };
+
+        let fine = leaves(0.01);
+        assert!(fine.len() > leaves(0.2).len());
+
+        // the leaf holding the attractor is smaller than the one in the far corner
+        let holding = |p: Vec2| {
+            fine.iter()
+                .find(|leaf| {
+                    (leaf.position.x..leaf.position.x + leaf.size).contains(&p.x)
+                        && (leaf.position.y..leaf.position.y + leaf.size).contains(&p.y)
+                })
+                .unwrap()
+                .size
+        };
+        assert!(holding(Vec2::new(101.0, 61.0)) < holding(Vec2::new(199.0, 1.0)));
+    }
+
+    #[test]
+    fn merges_add_up_like_on_a_grid() {
+        let mut quad = QuadField::new(100.0, 100.0, 2.0, 0.0);
+        quad.merge(&GradientField::new(1.0, 0.0))
+            .merge(&GradientField::new(1.0, std::f64::consts::FRAC_PI_2));
+        assert!(quad.sample([40.0, 60.0]).distance(Vec2::ONE) < 1e-5);
+        assert!(quad.get(10.0, 10.0).distance(Vec2::ONE) < 1e-5);
+    }
+}
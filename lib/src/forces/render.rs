@@ -0,0 +1,229 @@
+use crate::colors::Color;
+use crate::forces::map::FlowField;
+use crate::utils::hash2_unit;
+use nannou::color::Rgba;
+use nannou::glam::Vec2;
+use nannou::image::{Rgba as Pixel, RgbaImage};
+use nannou::prelude::{PI, TAU};
+use nannou::Draw;
+
+// Ways to look at a `FlowField` besides the plain line per cell of `FlowField::display`.
+//
+// Lines      : the default, a line plus a dot per cell
+// Arrows     : arrow glyphs, length scaled by the magnitude relative to the strongest cell
+// Angle      : cells filled on a hue wheel by direction
+// Magnitude  : cells filled with a dark-to-bright heatmap by magnitude
+// Streamlets : short lines traced through the field from every `spacing`-th cell
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DisplayMode {
+    Lines,
+    Arrows { scale: f32 },
+    Angle,
+    Magnitude,
+    Streamlets { steps: usize, spacing: usize },
+}
+
+impl DisplayMode {
+    // the next mode, handy for cycling through them with a key
+    pub fn next(&self) -> Self {
+        match self {
+            DisplayMode::Lines => DisplayMode::Arrows { scale: 1.0 },
+            DisplayMode::Arrows { .. } => DisplayMode::Angle,
+            DisplayMode::Angle => DisplayMode::Magnitude,
+            DisplayMode::Magnitude => DisplayMode::Streamlets {
+                steps: 20,
+                spacing: 2,
+            },
+            DisplayMode::Streamlets { .. } => DisplayMode::Lines,
+        }
+    }
+}
+
+impl FlowField {
+    pub fn display_mode(
+        &self,
+        draw: &Draw,
+        complete: bool,
+        mode: DisplayMode,
+        color: Option<Rgba>,
+    ) {
+        match mode {
+            DisplayMode::Lines => self.display(draw, complete, color),
+            DisplayMode::Arrows { scale } => self.display_arrows(draw, complete, scale, color),
+            DisplayMode::Angle => self.display_cells(draw, complete, |v, _| angle_color(v)),
+            DisplayMode::Magnitude => {
+                self.display_cells(draw, complete, |v, max| heat_color(v.length() / max))
+            }
+            DisplayMode::Streamlets { steps, spacing } => {
+                self.display_streamlets(draw, complete, steps, spacing, color)
+            }
+        }
+    }
+
+    pub fn display_arrows(&self, draw: &Draw, complete: bool, scale: f32, color: Option<Rgba>) {
+        let color: Rgba = color.unwrap_or(Rgba::new(0.0, 0.0, 0.0, 0.6));
+        let max = self.max_magnitude(complete);
+
+        self.each_displayable(complete, |i, j| {
+            let v = self.cell(i, j);
+            let length = v.length() / max * self.resolution() * scale;
+            if length <= f32::EPSILON {
+                return;
+            }
+
+            let start = self.point_at(i, j);
+            let end = start + v.normalize() * length;
+            let head = length * 0.3;
+            let back = -v.normalize();
+            let left = rotate(back, PI / 6.0) * head + end;
+            let right = rotate(back, -PI / 6.0) * head + end;
+
+            let points = [start, end, left, end, right];
+            let points = points.map(|p| if complete { self.map_to_visible(p) } else { p });
+            draw.line().start(points[0]).end(points[1]).color(color);
+            draw.line().start(points[2]).end(points[3]).color(color);
+            draw.line().start(points[4]).end(points[3]).color(color);
+        });
+    }
+
+    // trace short lines through the field with bilinear sampling, half a cell per step
+    pub fn display_streamlets(
+        &self,
+        draw: &Draw,
+        complete: bool,
+        steps: usize,
+        spacing: usize,
+        color: Option<Rgba>,
+    ) {
+        let color: Rgba = color.unwrap_or(Rgba::new(0.0, 0.0, 0.0, 0.4));
+        let spacing = spacing.max(1);
+        let step = self.resolution() / 2.0;
+
+        self.each_displayable(complete, |i, j| {
+            if i % spacing != 0 || j % spacing != 0 {
+                return;
+            }
+
+            let mut p = self.point_at(i, j);
+            let mut points = vec![p];
+            for _ in 0..steps {
+                let v = self.sample(p.into());
+                if v.length_squared() <= f32::EPSILON || !self.contains(p.into()) {
+                    break;
+                }
+                p += v.normalize() * step;
+                points.push(p);
+            }
+
+            if points.len() > 1 {
+                if complete {
+                    points.iter_mut().for_each(|p| *p = self.map_to_visible(*p));
+                }
+                draw.polyline().weight(1.0).points(points).color(color);
+            }
+        });
+    }
+
+    // line integral convolution of the visible field into a `width` x `height` image
+    //
+    // every pixel averages a seeded white noise texture along the flow line through it,
+    // `length` pixels forwards and backwards, which smears the noise along the flow
+    pub fn lic(&self, width: u32, height: u32, length: usize, seed: u32) -> RgbaImage {
+        let (w, h) = self.size();
+        let (sx, sy) = (w / width as f32, h / height as f32);
+        let noise = |x: f32, y: f32| {
+            let (px, py) = (x.floor() as i64, y.floor() as i64);
+            if px < 0 || py < 0 || px >= width as i64 || py >= height as i64 {
+                return None;
+            }
+            Some(hash2_unit(px, py, seed))
+        };
+
+        RgbaImage::from_fn(width, height, |px, py| {
+            let start = Vec2::new(px as f32 + 0.5, py as f32 + 0.5);
+            let mut total = noise(start.x, start.y).unwrap_or(0.0);
+            let mut count = 1.0;
+
+            for direction in [1.0, -1.0] {
+                let mut p = start;
+                for _ in 0..length {
+                    // world direction to pixels, which differ when the image is stretched
+                    let v = self.sample([p.x * sx, p.y * sy]) / Vec2::new(sx, sy);
+                    if v.length_squared() <= f32::EPSILON {
+                        break;
+                    }
+                    p += v.normalize() * direction;
+                    match noise(p.x, p.y) {
+                        Some(n) => {
+                            total += n;
+                            count += 1.0;
+                        }
+                        None => break,
+                    }
+                }
+            }
+
+            let value = (total / count * 255.0) as u8;
+            Pixel([value, value, value, 255])
+        })
+    }
+
+    fn display_cells(&self, draw: &Draw, complete: bool, color: impl Fn(Vec2, f32) -> Rgba) {
+        let max = self.max_magnitude(complete);
+        let size = if complete {
+            self.map_to_visible(Vec2::splat(self.resolution())) - self.map_to_visible(Vec2::ZERO)
+        } else {
+            Vec2::splat(self.resolution())
+        };
+
+        self.each_displayable(complete, |i, j| {
+            let mut center = self.point_at(i, j) + Vec2::splat(self.resolution() / 2.0);
+            if complete {
+                center = self.map_to_visible(center);
+            }
+
+            draw.rect()
+                .xy(center)
+                .wh(size)
+                .color(color(self.cell(i, j), max));
+        });
+    }
+
+    fn each_displayable(&self, complete: bool, mut f: impl FnMut(usize, usize)) {
+        let indices = self.displayable_bounds(complete);
+        for i in indices[0][0]..indices[1][0] {
+            for j in indices[0][1]..indices[1][1] {
+                f(i, j);
+            }
+        }
+    }
+
+    fn max_magnitude(&self, complete: bool) -> f32 {
+        let mut max = f32::EPSILON;
+        self.each_displayable(complete, |i, j| max = max.max(self.cell(i, j).length()));
+        max
+    }
+}
+
+fn rotate(v: Vec2, angle: f32) -> Vec2 {
+    let (sin, cos) = angle.sin_cos();
+    Vec2::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos)
+}
+
+// direction mapped onto the hue wheel, east is hue 0
+pub fn angle_color(v: Vec2) -> Rgba {
+    let hue = v.y.atan2(v.x).rem_euclid(TAU) / TAU;
+    Color::new(0.7, 0.45, hue, 1.0).into()
+}
+
+// t in [0, 1] mapped from dark purple through red to pale yellow
+pub fn heat_color(t: f32) -> Rgba {
+    let t = t.clamp(0.0, 1.0);
+    Color::new(
+        0.1 + 0.85 * t,
+        0.25 + 0.35 * (PI * t).sin(),
+        (0.85 + 0.3 * t).fract(),
+        1.0,
+    )
+    .into()
+}
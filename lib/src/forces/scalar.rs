@@ -0,0 +1,385 @@
+use crate::colors::Color;
+use crate::common::Seedable;
+use crate::forces::map::FlowField;
+use crate::utils::{perlin, PerlinNoise};
+use nannou::color::Rgba;
+use nannou::glam::Vec2;
+use nannou::image::{GrayImage, ImageResult, Luma};
+use nannou::math::map_range;
+use nannou::noise::NoiseFn;
+use nannou::Draw;
+use std::path::Path;
+
+// The scalar counterpart of `ForceField`: a number at field indices (i, j).
+// Closures `|i, j| ...` are sources too, which covers most one-off shapes.
+pub trait ScalarSource {
+    fn get(&self, i: f64, j: f64) -> f32;
+
+    // value at (i, j) at time t
+    // sources that do not change over time ignore t and fall back to `get`
+    fn get_at(&self, i: f64, j: f64, _t: f64) -> f32 {
+        self.get(i, j)
+    }
+}
+
+impl<F: Fn(f64, f64) -> f32> ScalarSource for F {
+    fn get(&self, i: f64, j: f64) -> f32 {
+        self(i, j)
+    }
+}
+
+// perlin noise in [-strength, strength]
+pub struct NoiseSource {
+    pub strength: f64,
+    pub scale: f64,
+    noise: PerlinNoise,
+    seed: u32,
+}
+
+impl NoiseSource {
+    pub fn new(strength: f64, scale: f64, seed: u32) -> Self {
+        NoiseSource {
+            strength,
+            scale,
+            noise: perlin(seed),
+            seed,
+        }
+    }
+}
+
+impl Seedable for NoiseSource {
+    fn set_seed(&mut self, seed: u32) -> &mut Self {
+        self.seed = seed;
+        self.noise = perlin(seed);
+        self
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+impl ScalarSource for NoiseSource {
+    fn get(&self, i: f64, j: f64) -> f32 {
+        (self.noise.get([i * self.scale, j * self.scale]) * self.strength) as f32
+    }
+
+    // time is the third noise dimension, so the values evolve smoothly with t
+    fn get_at(&self, i: f64, j: f64, t: f64) -> f32 {
+        (self.noise.get([i * self.scale, j * self.scale, t]) * self.strength) as f32
+    }
+}
+
+// A grid of numbers laid over the same cells as a `FlowField` of the same size,
+// resolution and extend: cell (i, j) covers the same world position in both.
+pub struct ScalarField {
+    cols: usize,
+    rows: usize,
+    resolution: f32,
+    extend: f32,
+    values: Vec<f32>,
+
+    width: f32,
+    height: f32,
+    _left_x: f32,
+    _right_x: f32,
+    _top_y: f32,
+    _bottom_y: f32,
+}
+
+impl ScalarField {
+    pub fn new(w: f32, h: f32, resolution: f32, extend: f32) -> Self {
+        let cols = f32::ceil(w * (1.0 + 2.0 * extend) / resolution) as usize;
+        let rows = f32::ceil(h * (1.0 + 2.0 * extend) / resolution) as usize;
+
+        ScalarField {
+            cols,
+            rows,
+            resolution,
+            extend,
+            values: vec![0.0; cols * rows],
+            width: w,
+            height: h,
+            _left_x: -w * extend,
+            _right_x: w * (1.0 + extend),
+            _top_y: -h * extend,
+            _bottom_y: h * (1.0 + extend),
+        }
+    }
+
+    // a zeroed grid matching the cells of a flow field
+    pub fn like(field: &FlowField) -> Self {
+        let (w, h) = field.size();
+        Self::new(w, h, field.resolution(), field.extend())
+    }
+
+    pub fn zero(&mut self) -> &mut Self {
+        self.fill(0.0)
+    }
+
+    pub fn fill(&mut self, value: f32) -> &mut Self {
+        self.values.fill(value);
+        self
+    }
+
+    pub fn merge(&mut self, source: &impl ScalarSource) -> &mut Self {
+        for (n, v) in self.values.iter_mut().enumerate() {
+            let (i, j) = (n % self.cols, n / self.cols);
+            *v += source.get(i as f64, j as f64);
+        }
+
+        self
+    }
+
+    // bake the source at time t, chain after `zero()` to animate
+    pub fn merge_at(&mut self, source: &impl ScalarSource, t: f64) -> &mut Self {
+        for (n, v) in self.values.iter_mut().enumerate() {
+            let (i, j) = (n % self.cols, n / self.cols);
+            *v += source.get_at(i as f64, j as f64, t);
+        }
+
+        self
+    }
+
+    // cell by cell arithmetic with another grid of the same size
+    pub fn add(&mut self, other: &ScalarField) -> &mut Self {
+        self.zip_with(other, |a, b| a + b)
+    }
+
+    pub fn sub(&mut self, other: &ScalarField) -> &mut Self {
+        self.zip_with(other, |a, b| a - b)
+    }
+
+    pub fn mul(&mut self, other: &ScalarField) -> &mut Self {
+        self.zip_with(other, |a, b| a * b)
+    }
+
+    pub fn zip_with(&mut self, other: &ScalarField, f: impl Fn(f32, f32) -> f32) -> &mut Self {
+        assert_eq!(
+            (self.cols, self.rows),
+            (other.cols, other.rows),
+            "scalar fields differ in size"
+        );
+        for (a, b) in self.values.iter_mut().zip(other.values.iter()) {
+            *a = f(*a, *b);
+        }
+        self
+    }
+
+    pub fn scale(&mut self, factor: f32) -> &mut Self {
+        self.apply(|v| v * factor)
+    }
+
+    pub fn offset(&mut self, amount: f32) -> &mut Self {
+        self.apply(|v| v + amount)
+    }
+
+    pub fn apply(&mut self, f: impl Fn(f32) -> f32) -> &mut Self {
+        for v in self.values.iter_mut() {
+            *v = f(*v);
+        }
+        self
+    }
+
+    pub fn clamp(&mut self, min: f32, max: f32) -> &mut Self {
+        self.apply(|v| v.clamp(min, max))
+    }
+
+    // linear map of [from_min, from_max] onto [to_min, to_max], values outside are extrapolated
+    pub fn remap(&mut self, from_min: f32, from_max: f32, to_min: f32, to_max: f32) -> &mut Self {
+        let span = (from_max - from_min).max(f32::EPSILON);
+        self.apply(|v| to_min + (v - from_min) / span * (to_max - to_min))
+    }
+
+    // stretch the current range onto [0, 1]
+    pub fn normalize(&mut self) -> &mut Self {
+        let (lo, hi) = self.min_max();
+        self.remap(lo, hi, 0.0, 1.0)
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> f32 {
+        self.values[j * self.cols + i]
+    }
+
+    pub fn set(&mut self, i: usize, j: usize, value: f32) {
+        self.values[j * self.cols + i] = value;
+    }
+
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn resolution(&self) -> f32 {
+        self.resolution
+    }
+
+    pub fn extend(&self) -> f32 {
+        self.extend
+    }
+
+    pub fn size(&self) -> (f32, f32) {
+        (self.width, self.height)
+    }
+
+    pub fn min_max(&self) -> (f32, f32) {
+        self.values
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(lo, hi), v| (lo.min(*v), hi.max(*v)))
+    }
+
+    // world position of the cell at (i, j)
+    pub fn point_at(&self, i: usize, j: usize) -> Vec2 {
+        Vec2::new(
+            i as f32 * self.resolution + self._left_x,
+            j as f32 * self.resolution + self._top_y,
+        )
+    }
+
+    pub fn index_at(&self, point: [f32; 2]) -> [usize; 2] {
+        let x = (point[0] - self._left_x) / self.resolution;
+        let y = (point[1] - self._top_y) / self.resolution;
+
+        let x = x.max(0.0).min(self.cols.saturating_sub(1) as f32);
+        let y = y.max(0.0).min(self.rows.saturating_sub(1) as f32);
+
+        [x as usize, y as usize]
+    }
+
+    // value of the cell nearest to a world position
+    pub fn value_at(&self, point: [f32; 2]) -> f32 {
+        if self.values.is_empty() {
+            return 0.0;
+        }
+        let [i, j] = self.index_at(point);
+        self.get(i, j)
+    }
+
+    // bilinear interpolation between the four cells around a world position
+    pub fn sample(&self, point: [f32; 2]) -> f32 {
+        if self.values.is_empty() {
+            return 0.0;
+        }
+        let x = ((point[0] - self._left_x) / self.resolution).clamp(0.0, (self.cols - 1) as f32);
+        let y = ((point[1] - self._top_y) / self.resolution).clamp(0.0, (self.rows - 1) as f32);
+
+        let (i0, j0) = (x.floor() as usize, y.floor() as usize);
+        let (i1, j1) = ((i0 + 1).min(self.cols - 1), (j0 + 1).min(self.rows - 1));
+        let (tx, ty) = (x - i0 as f32, y - j0 as f32);
+
+        let top = self.get(i0, j0) + (self.get(i1, j0) - self.get(i0, j0)) * tx;
+        let bottom = self.get(i0, j1) + (self.get(i1, j1) - self.get(i0, j1)) * tx;
+        top + (bottom - top) * ty
+    }
+
+    // direction of steepest increase per world unit, central differences like `FlowField::jacobian`
+    pub fn gradient(&self) -> FlowField {
+        let mut field = FlowField::new(self.width, self.height, self.resolution, self.extend);
+        for i in 0..self.cols {
+            for j in 0..self.rows {
+                let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.cols - 1));
+                let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.rows - 1));
+                let dx = ((i1 - i0) as f32 * self.resolution).max(f32::EPSILON);
+                let dy = ((j1 - j0) as f32 * self.resolution).max(f32::EPSILON);
+
+                field.set_cell(
+                    i,
+                    j,
+                    Vec2::new(
+                        (self.get(i1, j) - self.get(i0, j)) / dx,
+                        (self.get(i, j1) - self.get(i, j0)) / dy,
+                    ),
+                );
+            }
+        }
+        field
+    }
+
+    // the gradient turned a quarter, so the flow runs along the isolines
+    pub fn contour_flow(&self) -> FlowField {
+        let mut field = self.gradient();
+        for v in field.cells_mut() {
+            *v = Vec2::new(-v.y, v.x);
+        }
+        field
+    }
+
+    // one pixel per cell, the current range stretched over black to white
+    pub fn to_image(&self) -> GrayImage {
+        let (lo, hi) = self.min_max();
+        let span = (hi - lo).max(f32::EPSILON);
+        GrayImage::from_fn(self.cols as u32, self.rows as u32, |i, j| {
+            let v = (self.get(i as usize, j as usize) - lo) / span;
+            Luma([(v * 255.0).round() as u8])
+        })
+    }
+
+    pub fn save_image(&self, path: impl AsRef<Path>) -> ImageResult<()> {
+        self.to_image().save(path)
+    }
+
+    // cells colored on a diverging scale: blue below zero, red above, white at zero
+    // scaled by the largest absolute value, which suits divergence and curl
+    pub fn display(&self, draw: &Draw, complete: bool, alpha: f32) {
+        let (lo, hi) = self.min_max();
+        let max = lo.abs().max(hi.abs()).max(f32::EPSILON);
+        self.display_with(draw, complete, |v| diverging_color(v / max, alpha));
+    }
+
+    pub fn display_with(&self, draw: &Draw, complete: bool, color: impl Fn(f32) -> Rgba) {
+        let [from, to] = if complete {
+            [[0, 0], [self.cols, self.rows]]
+        } else {
+            [
+                self.index_at([0.0, 0.0]),
+                self.index_at([self.width, self.height]),
+            ]
+        };
+        let size = if complete {
+            self.map_to_visible(Vec2::splat(self.resolution)) - self.map_to_visible(Vec2::ZERO)
+        } else {
+            Vec2::splat(self.resolution)
+        };
+
+        for i in from[0]..to[0] {
+            for j in from[1]..to[1] {
+                let mut center = self.point_at(i, j) + Vec2::splat(self.resolution / 2.0);
+                if complete {
+                    center = self.map_to_visible(center);
+                }
+                draw.rect().xy(center).wh(size).color(color(self.get(i, j)));
+            }
+        }
+    }
+
+    fn map_to_visible(&self, point: Vec2) -> Vec2 {
+        Vec2::new(
+            map_range(point[0], self._left_x, self._right_x, 0.0, self.width),
+            map_range(point[1], self._top_y, self._bottom_y, 0.0, self.height),
+        )
+    }
+}
+
+// sampled in field indices, so a baked grid can be merged into another or used as a mask
+impl ScalarSource for ScalarField {
+    fn get(&self, i: f64, j: f64) -> f32 {
+        self.sample([
+            i as f32 * self.resolution + self._left_x,
+            j as f32 * self.resolution + self._top_y,
+        ])
+    }
+}
+
+// t in [-1, 1]: blue for negative, white at zero, red for positive
+pub fn diverging_color(t: f32, alpha: f32) -> Rgba {
+    let t = t.clamp(-1.0, 1.0);
+    let hue = if t < 0.0 { 0.72 } else { 0.08 };
+    Color::new(1.0 - 0.45 * t.abs(), 0.6 * t.abs(), hue, alpha).into()
+}
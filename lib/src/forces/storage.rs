@@ -0,0 +1,521 @@
+use crate::common::Seedable;
+use crate::forces::field::ForceField;
+use crate::forces::image::Raster;
+use crate::forces::map::FlowField;
+use nannou::glam::Vec2;
+use std::fs;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+// Saving and loading baked flow fields.
+//
+// Binary format (little endian), read back by `FlowField::load`:
+//
+//     magic       8 bytes  b"FLOWFLD\0"
+//     version     u32      1
+//     cols, rows  u32, u32
+//     resolution  f32
+//     extend      f32
+//     width       f32      visible width the field was built for
+//     height      f32
+//     seed        u32
+//     recipe      u32 length + utf-8 bytes
+//     vectors     cols * rows * (f32, f32), row by row (j outer, i inner)
+//
+// JSON and NumPy exports use the same row-major layout, with shape (rows, cols, 2) for .npy.
+
+const MAGIC: &[u8; 8] = b"FLOWFLD\0";
+const VERSION: u32 = 1;
+// largest grid any loader accepts, 16M cells is 128 MB of vectors
+const MAX_CELLS: usize = 1 << 24;
+
+impl FlowField {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(fs::File::create(path)?);
+        let (width, height) = self.size();
+
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        out.write_all(&(self.cols() as u32).to_le_bytes())?;
+        out.write_all(&(self.rows() as u32).to_le_bytes())?;
+        for value in [self.resolution(), self.extend(), width, height] {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        out.write_all(&self.seed().to_le_bytes())?;
+        out.write_all(&(self.recipe().len() as u32).to_le_bytes())?;
+        out.write_all(self.recipe().as_bytes())?;
+
+        for v in self.cells() {
+            out.write_all(&v.x.to_le_bytes())?;
+            out.write_all(&v.y.to_le_bytes())?;
+        }
+
+        out.flush()
+    }
+
+    // the whole file is read up front, so lengths in a corrupt header are checked against
+    // what is actually there instead of being allocated
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let mut reader = &bytes[..];
+
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a flow field file"));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(invalid(&format!(
+                "unsupported flow field version {}",
+                version
+            )));
+        }
+
+        let cols = read_u32(&mut reader)? as usize;
+        let rows = read_u32(&mut reader)? as usize;
+        let resolution = read_f32(&mut reader)?;
+        let extend = read_f32(&mut reader)?;
+        let width = read_f32(&mut reader)?;
+        let height = read_f32(&mut reader)?;
+        let seed = read_u32(&mut reader)?;
+        let recipe_len = read_u32(&mut reader)? as usize;
+        if recipe_len > reader.len() {
+            return Err(invalid("truncated recipe"));
+        }
+        let (recipe, mut reader) = reader.split_at(recipe_len);
+        let recipe = std::str::from_utf8(recipe).map_err(|_| invalid("recipe is not utf-8"))?;
+
+        if !resolution.is_finite() || resolution <= 0.0 {
+            return Err(invalid("resolution must be a positive number"));
+        }
+        if [extend, width, height]
+            .iter()
+            .any(|v| !v.is_finite() || *v < 0.0)
+        {
+            return Err(invalid("size and extend must be finite and not negative"));
+        }
+        if cells(cols, rows)?.checked_mul(8) != Some(reader.len()) {
+            return Err(invalid("vector data does not match the grid size"));
+        }
+        if FlowField::grid_size(width, height, resolution, extend) != (cols, rows) {
+            return Err(invalid("grid size does not match the stored dimensions"));
+        }
+
+        let mut field = FlowField::new(width, height, resolution, extend);
+        field.set_seed(seed).set_recipe(recipe);
+
+        for j in 0..rows {
+            for i in 0..cols {
+                let x = read_f32(&mut reader)?;
+                let y = read_f32(&mut reader)?;
+                field.set_cell(i, j, Vec2::new(x, y));
+            }
+        }
+
+        Ok(field)
+    }
+
+    pub fn save_json(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(fs::File::create(path)?);
+        let (width, height) = self.size();
+
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"version\": {},", VERSION)?;
+        writeln!(out, "  \"cols\": {},", self.cols())?;
+        writeln!(out, "  \"rows\": {},", self.rows())?;
+        writeln!(out, "  \"resolution\": {},", json_number(self.resolution()))?;
+        writeln!(out, "  \"extend\": {},", json_number(self.extend()))?;
+        writeln!(out, "  \"width\": {},", json_number(width))?;
+        writeln!(out, "  \"height\": {},", json_number(height))?;
+        writeln!(out, "  \"seed\": {},", self.seed())?;
+        writeln!(out, "  \"recipe\": {},", json_string(self.recipe()))?;
+        writeln!(out, "  \"field\": [")?;
+        for j in 0..self.rows() {
+            let row: Vec<String> = (0..self.cols())
+                .map(|i| {
+                    let v = self.cell(i, j);
+                    format!("[{},{}]", json_number(v.x), json_number(v.y))
+                })
+                .collect();
+            let separator = if j + 1 < self.rows() { "," } else { "" };
+            writeln!(out, "    [{}]{}", row.join(","), separator)?;
+        }
+        writeln!(out, "  ]")?;
+        writeln!(out, "}}")?;
+
+        out.flush()
+    }
+
+    // float32 array of shape (rows, cols, 2), loadable with `numpy.load`
+    pub fn save_npy(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(fs::File::create(path)?);
+        let shape = format!("({}, {}, 2)", self.rows(), self.cols());
+        write_npy_header(&mut out, &shape)?;
+
+        for v in self.cells() {
+            out.write_all(&v.x.to_le_bytes())?;
+            out.write_all(&v.y.to_le_bytes())?;
+        }
+
+        out.flush()
+    }
+}
+
+// Vector data from outside the sketch (numpy, other tools), usable as a force field.
+//
+// Like `ImageField`, one grid cell maps to one field cell with (0, 0) at the origin,
+// and `fit` stretches the grid over a range of field indices. Outside the grid the force is zero.
+pub struct GridField {
+    pub strength: f64,
+    cols: usize,
+    rows: usize,
+    vectors: Vec<Vec2>,
+    raster: Raster,
+}
+
+impl GridField {
+    // row-major vectors, fails unless there are `cols * rows` of them
+    pub fn new(cols: usize, rows: usize, vectors: Vec<Vec2>, strength: f64) -> io::Result<Self> {
+        if cols.checked_mul(rows) != Some(vectors.len()) {
+            return Err(invalid("expected cols * rows vectors"));
+        }
+        Ok(GridField {
+            strength,
+            cols,
+            rows,
+            vectors,
+            raster: Raster::new(cols, rows),
+        })
+    }
+
+    // float32 or float64 array of shape (rows, cols, 2), as written by `FlowField::save_npy`
+    pub fn from_npy(path: impl AsRef<Path>, strength: f64) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        if bytes.len() < 10 || &bytes[..6] != b"\x93NUMPY" {
+            return Err(invalid("not a .npy file"));
+        }
+
+        let (header_len, offset) = match bytes[6] {
+            1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+            2 | 3 if bytes.len() >= 12 => (
+                u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize,
+                12,
+            ),
+            _ => return Err(invalid("unsupported .npy version")),
+        };
+        let header = bytes
+            .get(offset..offset + header_len)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .ok_or_else(|| invalid("truncated .npy header"))?;
+
+        if header.contains("'fortran_order': True") {
+            return Err(invalid(".npy arrays in fortran order are not supported"));
+        }
+        let size = if header.contains("'<f4'") {
+            4
+        } else if header.contains("'<f8'") {
+            8
+        } else {
+            return Err(invalid(
+                ".npy dtype must be little endian float32 or float64",
+            ));
+        };
+
+        let shape = header
+            .split("'shape':")
+            .nth(1)
+            .and_then(|rest| rest.split(')').next())
+            .ok_or_else(|| invalid("missing shape in .npy header"))?;
+        let dims: Vec<usize> = shape
+            .trim_start_matches([' ', '('])
+            .split(',')
+            .filter(|d| !d.trim().is_empty())
+            .map(|d| d.trim().parse().map_err(|_| invalid("invalid .npy shape")))
+            .collect::<io::Result<_>>()?;
+        let (rows, cols) = match dims[..] {
+            [rows, cols, 2] => (rows, cols),
+            _ => return Err(invalid("expected a .npy array of shape (rows, cols, 2)")),
+        };
+
+        let data = &bytes[offset + header_len..];
+        let needed = cells(cols, rows)?
+            .checked_mul(2 * size)
+            .ok_or_else(|| invalid("invalid .npy shape"))?;
+        if data.len() < needed {
+            return Err(invalid("truncated .npy data"));
+        }
+        let value = |n: usize| -> f32 {
+            let b = &data[n * size..(n + 1) * size];
+            match size {
+                4 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+                _ => f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32,
+            }
+        };
+        let vectors = (0..rows * cols)
+            .map(|n| Vec2::new(value(2 * n), value(2 * n + 1)))
+            .collect();
+
+        Self::new(cols, rows, vectors, strength)
+    }
+
+    // one vector per line as `i,j,vx,vy`; blank lines, `#` comments and a header line are skipped,
+    // the grid size is taken from the largest indices and missing cells are zero
+    pub fn from_csv(path: impl AsRef<Path>, strength: f64) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let mut entries = Vec::new();
+
+        for (n, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match csv_entry(line) {
+                Some(entry) => entries.push(entry),
+                // tolerate a header line such as `i,j,vx,vy`
+                None if entries.is_empty() && n == 0 => continue,
+                None => {
+                    return Err(invalid(&format!(
+                        "line {}: expected 'i,j,vx,vy', got '{}'",
+                        n + 1,
+                        line
+                    )))
+                }
+            }
+        }
+
+        let size = |index: usize| {
+            index
+                .checked_add(1)
+                .ok_or_else(|| invalid("index too large"))
+        };
+        let (mut cols, mut rows) = (0, 0);
+        for (i, j, _, _) in &entries {
+            cols = size(*i)?.max(cols);
+            rows = size(*j)?.max(rows);
+        }
+        let mut vectors = vec![Vec2::ZERO; cells(cols, rows)?];
+        for (i, j, x, y) in entries {
+            vectors[j * cols + i] = Vec2::new(x, y);
+        }
+
+        Self::new(cols, rows, vectors, strength)
+    }
+
+    // stretch the grid over the field indices [from, to)
+    pub fn fit(&mut self, bounds: [[usize; 2]; 2]) -> &mut Self {
+        self.raster.fit(bounds);
+        self
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+}
+
+impl ForceField for GridField {
+    fn get(&self, i: f64, j: f64) -> Vec2 {
+        self.raster
+            .sample(&self.vectors, i, j)
+            .map_or(Vec2::ZERO, |v| v * self.strength as f32)
+    }
+}
+
+fn csv_entry(line: &str) -> Option<(usize, usize, f32, f32)> {
+    let mut values = line.split(',').map(|v| v.trim());
+    let entry = (
+        values.next()?.parse().ok()?,
+        values.next()?.parse().ok()?,
+        values.next()?.parse().ok()?,
+        values.next()?.parse().ok()?,
+    );
+    values.next().is_none().then_some(entry)
+}
+
+fn write_npy_header(out: &mut impl Write, shape: &str) -> io::Result<()> {
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': {}, }}",
+        shape
+    );
+    // magic (6) + version (2) + length (2) + header + newline, padded to 64 bytes
+    let unpadded = 10 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    out.write_all(b"\x93NUMPY\x01\x00")?;
+    out.write_all(&(header.len() as u16).to_le_bytes())?;
+    out.write_all(header.as_bytes())
+}
+
+// JSON has no NaN or infinity, those become null
+fn json_number(value: f32) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_f32(reader: &mut impl Read) -> io::Result<f32> {
+    read_u32(reader).map(f32::from_bits)
+}
+
+// cols * rows, unless that overflows or is more than `MAX_CELLS`
+fn cells(cols: usize, rows: usize) -> io::Result<usize> {
+    match cols.checked_mul(rows) {
+        Some(n) if n <= MAX_CELLS => Ok(n),
+        _ => Err(invalid(&format!(
+            "grid of {} x {} cells is too large",
+            cols, rows
+        ))),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("lib-storage-{}-{}", std::process::id(), name))
+    }
+
+    fn sample_field() -> FlowField {
+        let mut field = FlowField::new(64.0, 48.0, 8.0, 0.25);
+        for j in 0..field.rows() {
+            for i in 0..field.cols() {
+                field.set_cell(i, j, Vec2::new(i as f32 * 0.5, j as f32 - 3.0));
+            }
+        }
+        field.set_seed(7).set_recipe("gradient \"test\"");
+        field
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let field = sample_field();
+        let path = temp_path("round-trip.bin");
+        field.save(&path).unwrap();
+        let loaded = FlowField::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!((loaded.cols(), loaded.rows()), (field.cols(), field.rows()));
+        assert_eq!(loaded.resolution(), field.resolution());
+        assert_eq!(loaded.extend(), field.extend());
+        assert_eq!(loaded.size(), field.size());
+        assert_eq!(loaded.seed(), 7);
+        assert_eq!(loaded.recipe(), field.recipe());
+        assert_eq!(loaded.cells(), field.cells());
+    }
+
+    #[test]
+    fn load_rejects_corrupt_files() {
+        let path = temp_path("corrupt.bin");
+        sample_field().save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        let corrupt = |edit: &dyn Fn(&mut Vec<u8>)| {
+            let mut bytes = bytes.clone();
+            edit(&mut bytes);
+            fs::write(&path, &bytes).unwrap();
+            FlowField::load(&path).is_err()
+        };
+        // truncated vectors, huge recipe length, zero and NaN resolution, huge grid
+        assert!(corrupt(&|b| b.truncate(b.len() - 3)));
+        assert!(corrupt(
+            &|b| b[40..44].copy_from_slice(&u32::MAX.to_le_bytes())
+        ));
+        assert!(corrupt(&|b| b[20..24].copy_from_slice(&0f32.to_le_bytes())));
+        assert!(corrupt(
+            &|b| b[20..24].copy_from_slice(&f32::NAN.to_le_bytes())
+        ));
+        assert!(corrupt(
+            &|b| b[12..16].copy_from_slice(&u32::MAX.to_le_bytes())
+        ));
+        assert!(corrupt(&|b| b.truncate(20)));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn npy_round_trip() {
+        let field = sample_field();
+        let path = temp_path("field.npy");
+        field.save_npy(&path).unwrap();
+        let grid = GridField::from_npy(&path, 1.0).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(grid.dimensions(), (field.cols(), field.rows()));
+        for j in 0..field.rows() {
+            for i in 0..field.cols() {
+                assert_eq!(grid.get(i as f64, j as f64), field.cell(i, j));
+            }
+        }
+    }
+
+    #[test]
+    fn json_writes_null_for_non_finite_values() {
+        let mut field = sample_field();
+        field.set_cell(1, 1, Vec2::new(f32::NAN, f32::INFINITY));
+        let path = temp_path("field.json");
+        field.save_json(&path).unwrap();
+        let json = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(json.contains("[null,null]"));
+        assert!(!json.contains("NaN") && !json.contains("inf"));
+    }
+
+    #[test]
+    fn csv_sparse_grid_and_oversized_indices() {
+        let path = temp_path("field.csv");
+        fs::write(&path, "i,j,vx,vy\n# sparse\n0,0,1,2\n2,1,3,4\n").unwrap();
+        let grid = GridField::from_csv(&path, 1.0).unwrap();
+        assert_eq!(grid.dimensions(), (3, 2));
+        assert_eq!(grid.get(2.0, 1.0), Vec2::new(3.0, 4.0));
+        assert_eq!(grid.get(1.0, 0.0), Vec2::ZERO);
+
+        for line in [
+            format!("{},0,1,1", usize::MAX),
+            "200000,200000,1,1".to_string(),
+        ] {
+            fs::write(&path, line).unwrap();
+            let err = GridField::from_csv(&path, 1.0).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn grid_field_checks_its_size() {
+        assert!(GridField::new(2, 2, vec![Vec2::ZERO; 3], 1.0).is_err());
+        assert!(GridField::new(2, 2, vec![Vec2::ZERO; 4], 1.0).is_ok());
+    }
+}
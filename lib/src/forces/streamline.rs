@@ -0,0 +1,302 @@
+use crate::forces::map::FlowField;
+use nannou::glam::Vec2;
+
+// Lines traced through a baked `FlowField`, following its direction (not its speed)
+// in steps of `step` world units from a seed point.
+//
+// A line stops when it leaves the field, when the field gets slower than `min_speed`
+// (a stagnation point), when it comes back within `self_distance` of itself,
+// or after `max_steps` steps in each direction.
+//
+//     let mut tracer = Tracer::new(2.0, 500);
+//     tracer.integrator(Integrator::Rk4).direction(Direction::Both);
+//     let lines = tracer.trace_all(&field, &seeds);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Integrator {
+    // one sample per step, cheap but drifts outwards on curves
+    Euler,
+    // two samples per step (RK2)
+    Midpoint,
+    // four samples per step, the usual choice
+    Rk4,
+    // Runge-Kutta-Fehlberg 4(5): six samples per step, the step shrinks on tight curves
+    // and grows on straight parts so the error per step stays under `tolerance` world units
+    Rk45 { tolerance: f32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward,
+    Both,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tracer {
+    step: f32,
+    max_steps: usize,
+    integrator: Integrator,
+    direction: Direction,
+    min_speed: f32,
+    self_distance: f32,
+}
+
+impl Tracer {
+    pub const DEFAULT_MIN_SPEED: f32 = 1e-4;
+
+    pub fn new(step: f32, max_steps: usize) -> Self {
+        Tracer {
+            step: step.max(f32::EPSILON),
+            max_steps,
+            integrator: Integrator::Rk4,
+            direction: Direction::Forward,
+            min_speed: Self::DEFAULT_MIN_SPEED,
+            self_distance: 0.0,
+        }
+    }
+
+    pub fn integrator(&mut self, integrator: Integrator) -> &mut Self {
+        self.integrator = integrator;
+        self
+    }
+
+    pub fn direction(&mut self, direction: Direction) -> &mut Self {
+        self.direction = direction;
+        self
+    }
+
+    // field magnitude under which a line is considered stuck
+    pub fn stagnation(&mut self, min_speed: f32) -> &mut Self {
+        self.min_speed = min_speed.max(0.0);
+        self
+    }
+
+    // stop a line that comes back within `distance` of itself, e.g. around a center
+    // 0 turns the check off
+    pub fn self_distance(&mut self, distance: f32) -> &mut Self {
+        self.self_distance = distance.max(0.0);
+        self
+    }
+
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+
+    pub fn trace(&self, field: &FlowField, seed: Vec2) -> Vec<Vec2> {
+        self.trace_with(field, seed, |_, _| false)
+    }
+
+    // one line per seed, lines that could not leave their seed are left out
+    pub fn trace_all(&self, field: &FlowField, seeds: &[Vec2]) -> Vec<Vec<Vec2>> {
+        seeds
+            .iter()
+            .map(|seed| self.trace(field, *seed))
+            .filter(|line| line.len() > 1)
+            .collect()
+    }
+
+    // like `trace`, with an extra stop condition checked before every new point:
+    // `stop(point, line so far)` returning true ends the line in that direction
+    pub fn trace_with(
+        &self,
+        field: &FlowField,
+        seed: Vec2,
+        mut stop: impl FnMut(Vec2, &[Vec2]) -> bool,
+    ) -> Vec<Vec2> {
+        if !field.contains(seed.into()) {
+            return Vec::new();
+        }
+
+        match self.direction {
+            Direction::Forward => self.follow(field, vec![seed], 1.0, &mut stop),
+            Direction::Backward => self.follow(field, vec![seed], -1.0, &mut stop),
+            Direction::Both => {
+                let mut line = self.follow(field, vec![seed], -1.0, &mut stop);
+                line.reverse();
+                self.follow(field, line, 1.0, &mut stop)
+            }
+        }
+    }
+
+    // extend `line` from its last point, along the flow (sign 1) or against it (sign -1)
+    fn follow(
+        &self,
+        field: &FlowField,
+        mut line: Vec<Vec2>,
+        sign: f32,
+        stop: &mut impl FnMut(Vec2, &[Vec2]) -> bool,
+    ) -> Vec<Vec2> {
+        let direction = |p: Vec2| field.sample(p.into()).normalize_or_zero() * sign;
+        let mut p = *line.last().unwrap();
+        let mut h = self.step;
+
+        for _ in 0..self.max_steps {
+            if field.sample(p.into()).length() < self.min_speed {
+                break;
+            }
+
+            let next = match self.integrator {
+                Integrator::Euler => p + direction(p) * h,
+                Integrator::Midpoint => {
+                    let k1 = direction(p);
+                    p + direction(p + k1 * h / 2.0) * h
+                }
+                Integrator::Rk4 => {
+                    let k1 = direction(p);
+                    let k2 = direction(p + k1 * h / 2.0);
+                    let k3 = direction(p + k2 * h / 2.0);
+                    let k4 = direction(p + k3 * h);
+                    p + (k1 + 2.0 * k2 + 2.0 * k3 + k4) * h / 6.0
+                }
+                Integrator::Rk45 { tolerance } => {
+                    let (next, used) = self.rk45(&direction, p, h, tolerance);
+                    h = used;
+                    next
+                }
+            };
+
+            if next.distance_squared(p) <= f32::EPSILON
+                || !field.contains(next.into())
+                || self.near_itself(next, &line)
+                || stop(next, &line)
+            {
+                break;
+            }
+
+            line.push(next);
+            p = next;
+        }
+
+        line
+    }
+
+    // one accepted Fehlberg step from p, trying h first; returns the point and the next step size
+    fn rk45(&self, f: &impl Fn(Vec2) -> Vec2, p: Vec2, mut h: f32, tolerance: f32) -> (Vec2, f32) {
+        let (min, max) = (self.step / 8.0, self.step * 4.0);
+        let tolerance = tolerance.max(f32::EPSILON);
+
+        loop {
+            let k1 = f(p);
+            let k2 = f(p + h * (k1 / 4.0));
+            let k3 = f(p + h * (3.0 / 32.0 * k1 + 9.0 / 32.0 * k2));
+            let k4 =
+                f(p + h * (1932.0 / 2197.0 * k1 - 7200.0 / 2197.0 * k2 + 7296.0 / 2197.0 * k3));
+            let k5 =
+                f(p + h
+                    * (439.0 / 216.0 * k1 - 8.0 * k2 + 3680.0 / 513.0 * k3 - 845.0 / 4104.0 * k4));
+            let k6 = f(p + h
+                * (-8.0 / 27.0 * k1 + 2.0 * k2 - 3544.0 / 2565.0 * k3 + 1859.0 / 4104.0 * k4
+                    - 11.0 / 40.0 * k5));
+
+            let fourth = p + h
+                * (25.0 / 216.0 * k1 + 1408.0 / 2565.0 * k3 + 2197.0 / 4104.0 * k4 - k5 / 5.0);
+            let fifth = p + h
+                * (16.0 / 135.0 * k1 + 6656.0 / 12825.0 * k3 + 28561.0 / 56430.0 * k4
+                    - 9.0 / 50.0 * k5
+                    + 2.0 / 55.0 * k6);
+
+            let error = fifth.distance(fourth);
+            let factor = if error <= f32::EPSILON {
+                4.0
+            } else {
+                (0.9 * (tolerance / error).powf(0.2)).clamp(0.2, 4.0)
+            };
+
+            if error <= tolerance || h <= min {
+                return (fifth, (h * factor).clamp(min, max));
+            }
+            h = (h * factor).max(min);
+        }
+    }
+
+    // whether p comes within `self_distance` of the line, ignoring the last stretch of it
+    // (twice the distance, measured along the line) that p naturally continues from
+    fn near_itself(&self, p: Vec2, line: &[Vec2]) -> bool {
+        if self.self_distance <= 0.0 {
+            return false;
+        }
+
+        let mut along = p.distance(*line.last().unwrap());
+        for pair in line.windows(2).rev() {
+            if along > 2.0 * self.self_distance && p.distance(pair[0]) < self.self_distance {
+                return true;
+            }
+            along += pair[0].distance(pair[1]);
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 100 x 100 field with 2 unit cells, filled with v(p) at every cell position
+    fn field(v: impl Fn(Vec2) -> Vec2) -> FlowField {
+        let mut field = FlowField::new(100.0, 100.0, 2.0, 0.0);
+        for j in 0..field.rows() {
+            for i in 0..field.cols() {
+                field.set_cell(i, j, v(field.point_at(i, j)));
+            }
+        }
+        field
+    }
+
+    #[test]
+    fn uniform_fields_trace_straight_lines() {
+        let field = field(|_| Vec2::new(3.0, 0.0));
+        let seed = Vec2::new(50.0, 40.0);
+        let mut tracer = Tracer::new(2.0, 10);
+
+        let forward = tracer.trace(&field, seed);
+        assert_eq!(forward.len(), 11);
+        assert!(forward.last().unwrap().distance(Vec2::new(70.0, 40.0)) < 1e-3);
+
+        let backward = tracer.direction(Direction::Backward).trace(&field, seed);
+        assert!(backward.last().unwrap().distance(Vec2::new(30.0, 40.0)) < 1e-3);
+
+        let both = tracer.direction(Direction::Both).trace(&field, seed);
+        assert_eq!(both.len(), 21);
+        assert!(both.first().unwrap().distance(Vec2::new(30.0, 40.0)) < 1e-3);
+        assert!(both.last().unwrap().distance(Vec2::new(70.0, 40.0)) < 1e-3);
+    }
+
+    #[test]
+    fn lines_stop_at_the_edge_and_at_stagnation() {
+        let uniform = field(|_| Vec2::X);
+        let line = Tracer::new(2.0, 1000).trace(&uniform, Vec2::new(50.0, 50.0));
+        assert!(line.len() < 30);
+        assert!(line.iter().all(|p| uniform.contains((*p).into())));
+
+        let still = field(|_| Vec2::ZERO);
+        let tracer = Tracer::new(2.0, 100);
+        assert_eq!(tracer.trace(&still, Vec2::new(50.0, 50.0)).len(), 1);
+        assert!(tracer
+            .trace_all(&still, &[Vec2::new(50.0, 50.0)])
+            .is_empty());
+        assert!(tracer.trace(&uniform, Vec2::new(-10.0, 50.0)).is_empty());
+    }
+
+    #[test]
+    fn integrators_follow_a_circle_and_stop_near_themselves() {
+        let center = Vec2::new(50.0, 50.0);
+        let rotation = field(|p| Vec2::new(-(p.y - center.y), p.x - center.x));
+        let seed = Vec2::new(70.0, 50.0);
+
+        for integrator in [Integrator::Rk4, Integrator::Rk45 { tolerance: 1e-3 }] {
+            let line = Tracer::new(1.0, 100)
+                .integrator(integrator)
+                .trace(&rotation, seed);
+            for p in &line {
+                assert!((p.distance(center) - 20.0).abs() < 0.5, "{:?}", integrator);
+            }
+        }
+
+        // a full turn is about 126 steps, the line closes instead of running to max_steps
+        let closed = Tracer::new(1.0, 1000)
+            .self_distance(1.5)
+            .trace(&rotation, seed);
+        assert!(closed.len() > 100 && closed.len() < 140, "{}", closed.len());
+    }
+}
@@ -0,0 +1,455 @@
+use crate::common::Seedable;
+use crate::forces::ode::{Ode, Plane};
+use crate::utils::{perlin, PerlinNoise};
+use nannou::glam::{Vec2, Vec3};
+use nannou::noise::NoiseFn;
+use nannou::prelude::{PI, TAU};
+
+// Volumetric flows: the 3D counterparts of `ForceField` and `FlowField`, a tracer for
+// streamlines through them and a camera to turn those into 2D polylines for drawing.
+//
+// Field indices (i, j, k) map to world positions (i, j, k) * resolution, starting at the origin.
+//
+//     let mut volume = FlowField3d::new(400.0, 400.0, 400.0, 10.0);
+//     volume.merge(&PerlinField3d::new(1.0, 0.05, seed));
+//     let line = volume.streamline(Vec3::new(200.0, 200.0, 200.0), 2.0, 500);
+//     for part in camera.project_polyline(&line) { ... }
+
+pub trait ForceField3d {
+    fn get(&self, i: f64, j: f64, k: f64) -> Vec3;
+
+    // force at (i, j, k) at time t
+    // fields that do not change over time ignore t and fall back to `get`
+    fn get_at(&self, i: f64, j: f64, k: f64, _t: f64) -> Vec3 {
+        self.get(i, j, k)
+    }
+}
+
+// Two noise channels pick the direction on the sphere, the field has unit length times strength.
+pub struct PerlinField3d {
+    pub strength: f64,
+    pub scale: f64,
+    noise: PerlinNoise,
+    seed: u32,
+}
+
+impl PerlinField3d {
+    // offset between the two noise channels, far enough apart to look unrelated
+    const CHANNEL_OFFSET: f64 = 1000.0;
+
+    pub fn new(strength: f64, scale: f64, seed: u32) -> Self {
+        PerlinField3d {
+            strength,
+            scale,
+            noise: perlin(seed),
+            seed,
+        }
+    }
+
+    fn noise_to_force(&self, theta: f64, phi: f64) -> Vec3 {
+        let theta = theta as f32 * TAU;
+        let phi = (phi as f32 * 0.5 + 0.5) * PI;
+        Vec3::new(phi.sin() * theta.cos(), phi.sin() * theta.sin(), phi.cos())
+            * self.strength as f32
+    }
+}
+
+impl Seedable for PerlinField3d {
+    fn set_seed(&mut self, seed: u32) -> &mut Self {
+        self.seed = seed;
+        self.noise = perlin(seed);
+        self
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+impl ForceField3d for PerlinField3d {
+    fn get(&self, i: f64, j: f64, k: f64) -> Vec3 {
+        let p = [i * self.scale, j * self.scale, k * self.scale];
+        let q = [p[0] + Self::CHANNEL_OFFSET, p[1], p[2]];
+        self.noise_to_force(self.noise.get(p), self.noise.get(q))
+    }
+
+    // time is the fourth noise dimension, so the field evolves smoothly with t
+    fn get_at(&self, i: f64, j: f64, k: f64, t: f64) -> Vec3 {
+        let p = [i * self.scale, j * self.scale, k * self.scale, t];
+        let q = [p[0] + Self::CHANNEL_OFFSET, p[1], p[2], t];
+        self.noise_to_force(self.noise.get(p), self.noise.get(q))
+    }
+}
+
+// Pulls towards (or, with a negative strength, pushes away from) a point,
+// decaying with distance like `AttractorField`.
+pub struct AttractorField3d {
+    pub strength: f64,
+    i: f64,
+    j: f64,
+    k: f64,
+}
+
+impl AttractorField3d {
+    pub fn new(strength: f64, i: usize, j: usize, k: usize) -> Self {
+        AttractorField3d {
+            strength,
+            i: i as f64,
+            j: j as f64,
+            k: k as f64,
+        }
+    }
+}
+
+impl ForceField3d for AttractorField3d {
+    fn get(&self, i: f64, j: f64, k: f64) -> Vec3 {
+        let d = Vec3::new(
+            (self.i - i) as f32,
+            (self.j - j) as f32,
+            (self.k - k) as f32,
+        );
+        let decay = self.strength as f32 / d.length().max(1.0).powf(1.0 / 3.0);
+        d.normalize_or_zero() * decay
+    }
+}
+
+// A 3D system (Lorenz, Rossler, Thomas) over a box of field indices [from, to),
+// mapped onto its state space window. The axes keep their orientation: x along i, y along j, z along k.
+pub struct OdeField3d {
+    pub strength: f64,
+    pub normalize: bool,
+    ode: Ode,
+    bounds: [[f64; 3]; 2],
+    window: [[f64; 3]; 2],
+}
+
+impl OdeField3d {
+    pub fn new(ode: Ode, strength: f64, bounds: [[usize; 3]; 2]) -> Self {
+        let [from, to] = bounds;
+        let [[x0, y0], [x1, y1]] = ode.default_window(Plane::XY);
+        let [[_, z0], [_, z1]] = ode.default_window(Plane::XZ);
+
+        OdeField3d {
+            strength,
+            normalize: true,
+            ode,
+            bounds: [from.map(|v| v as f64), to.map(|v| v as f64)],
+            window: [[x0, y0, z0], [x1, y1, z1]],
+        }
+    }
+
+    // the box [min, max] of state space covered by the field
+    pub fn window(&mut self, min: [f64; 3], max: [f64; 3]) -> &mut Self {
+        self.window = [min, max];
+        self
+    }
+
+    // true  : unit vectors (times strength), shows the direction field
+    // false : raw velocities (times strength), fast regions dominate
+    pub fn normalize(&mut self, normalize: bool) -> &mut Self {
+        self.normalize = normalize;
+        self
+    }
+
+    // state space coordinates of field indices (i, j, k)
+    pub fn to_state(&self, i: f64, j: f64, k: f64) -> [f64; 3] {
+        let [from, to] = self.bounds;
+        let [min, max] = self.window;
+        let p = [i, j, k];
+        [0, 1, 2].map(|a| min[a] + (p[a] - from[a]) / (to[a] - from[a]) * (max[a] - min[a]))
+    }
+}
+
+impl ForceField3d for OdeField3d {
+    fn get(&self, i: f64, j: f64, k: f64) -> Vec3 {
+        let [x, y, z] = self.to_state(i, j, k);
+        let d = self.ode.derivative3(x, y, z);
+
+        // state space velocity in field indices per unit of time
+        let [from, to] = self.bounds;
+        let [min, max] = self.window;
+        let [vx, vy, vz] = [0, 1, 2].map(|a| (d[a] * (to[a] - from[a]) / (max[a] - min[a])) as f32);
+        let velocity = Vec3::new(vx, vy, vz);
+
+        let velocity = if self.normalize {
+            velocity.normalize_or_zero()
+        } else {
+            velocity
+        };
+
+        if velocity.is_finite() {
+            velocity * self.strength as f32
+        } else {
+            Vec3::ZERO
+        }
+    }
+}
+
+pub struct FlowField3d {
+    cols: usize,
+    rows: usize,
+    layers: usize,
+    resolution: f32,
+    // the vector of cell (i, j, k) is at `(k * rows + j) * cols + i`
+    field: Vec<Vec3>,
+
+    width: f32,
+    height: f32,
+    depth: f32,
+}
+
+impl FlowField3d {
+    pub fn new(w: f32, h: f32, d: f32, resolution: f32) -> Self {
+        let cols = f32::ceil(w / resolution) as usize + 1;
+        let rows = f32::ceil(h / resolution) as usize + 1;
+        let layers = f32::ceil(d / resolution) as usize + 1;
+
+        FlowField3d {
+            cols,
+            rows,
+            layers,
+            resolution,
+            field: vec![Vec3::ZERO; cols * rows * layers],
+            width: w,
+            height: h,
+            depth: d,
+        }
+    }
+
+    pub fn zero(&mut self) -> &mut Self {
+        self.field.fill(Vec3::ZERO);
+        self
+    }
+
+    pub fn merge(&mut self, force: &impl ForceField3d) -> &mut Self {
+        let (cols, rows) = (self.cols, self.rows);
+        for (n, v) in self.field.iter_mut().enumerate() {
+            let (i, j, k) = (n % cols, n / cols % rows, n / (cols * rows));
+            *v += force.get(i as f64, j as f64, k as f64);
+        }
+
+        self
+    }
+
+    // bake the field at time t, chain after `zero()` to animate
+    pub fn merge_at(&mut self, force: &impl ForceField3d, t: f64) -> &mut Self {
+        let (cols, rows) = (self.cols, self.rows);
+        for (n, v) in self.field.iter_mut().enumerate() {
+            let (i, j, k) = (n % cols, n / cols % rows, n / (cols * rows));
+            *v += force.get_at(i as f64, j as f64, k as f64, t);
+        }
+
+        self
+    }
+
+    pub fn cell(&self, i: usize, j: usize, k: usize) -> Vec3 {
+        self.field[(k * self.rows + j) * self.cols + i]
+    }
+
+    pub fn set_cell(&mut self, i: usize, j: usize, k: usize, force: Vec3) {
+        self.field[(k * self.rows + j) * self.cols + i] = force;
+    }
+
+    // world position of the cell at (i, j, k)
+    pub fn point_at(&self, i: usize, j: usize, k: usize) -> Vec3 {
+        Vec3::new(i as f32, j as f32, k as f32) * self.resolution
+    }
+
+    pub fn index_at(&self, point: Vec3) -> [usize; 3] {
+        let p = (point / self.resolution).max(Vec3::ZERO);
+        [
+            (p.x as usize).min(self.cols - 1),
+            (p.y as usize).min(self.rows - 1),
+            (p.z as usize).min(self.layers - 1),
+        ]
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.cmpge(Vec3::ZERO).all()
+            && point
+                .cmple(Vec3::new(self.width, self.height, self.depth))
+                .all()
+    }
+
+    // trilinear interpolation between the eight cells around a world position
+    pub fn sample(&self, point: Vec3) -> Vec3 {
+        let max = Vec3::new(
+            (self.cols - 1) as f32,
+            (self.rows - 1) as f32,
+            (self.layers - 1) as f32,
+        );
+        let p = (point / self.resolution).clamp(Vec3::ZERO, max);
+
+        let (i0, j0, k0) = (p.x as usize, p.y as usize, p.z as usize);
+        let i1 = (i0 + 1).min(self.cols - 1);
+        let j1 = (j0 + 1).min(self.rows - 1);
+        let k1 = (k0 + 1).min(self.layers - 1);
+        let t = p - Vec3::new(i0 as f32, j0 as f32, k0 as f32);
+
+        let layer = |k| {
+            let top = self.cell(i0, j0, k).lerp(self.cell(i1, j0, k), t.x);
+            let bottom = self.cell(i0, j1, k).lerp(self.cell(i1, j1, k), t.x);
+            top.lerp(bottom, t.y)
+        };
+        layer(k0).lerp(layer(k1), t.z)
+    }
+
+    // follow the flow from `start` (world position) with RK4 steps of `step` world units,
+    // until it leaves the volume, stalls, or after `steps` steps
+    pub fn streamline(&self, start: Vec3, step: f32, steps: usize) -> Vec<Vec3> {
+        let direction = |p: Vec3| self.sample(p).normalize_or_zero();
+        let mut points = vec![start];
+        let mut p = start;
+
+        for _ in 0..steps {
+            let k1 = direction(p);
+            let k2 = direction(p + k1 * step / 2.0);
+            let k3 = direction(p + k2 * step / 2.0);
+            let k4 = direction(p + k3 * step);
+            let delta = (k1 + 2.0 * k2 + 2.0 * k3 + k4) * step / 6.0;
+            if delta.length_squared() <= f32::EPSILON || !self.contains(p + delta) {
+                break;
+            }
+
+            p += delta;
+            points.push(p);
+        }
+
+        points
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn layers(&self) -> usize {
+        self.layers
+    }
+
+    pub fn resolution(&self) -> f32 {
+        self.resolution
+    }
+
+    pub fn size(&self) -> (f32, f32, f32) {
+        (self.width, self.height, self.depth)
+    }
+
+    // center of the volume, a natural camera target
+    pub fn center(&self) -> Vec3 {
+        Vec3::new(self.width, self.height, self.depth) / 2.0
+    }
+}
+
+// Orthographic : parallel lines stay parallel, `scale` is screen units per world unit
+// Perspective  : vertical field of view in radians, things shrink with distance
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    Orthographic { scale: f32 },
+    Perspective { fov: f32 },
+}
+
+// A point on screen, with its distance from the camera along the view direction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Projected {
+    pub point: Vec2,
+    pub depth: f32,
+}
+
+// Looks from `eye` at `target` onto a `width` x `height` screen, with (0, 0) at the top left
+// and y pointing down like the 2D fields.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    pub projection: Projection,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Camera {
+    // nothing closer than this to the eye is drawn in perspective
+    pub const NEAR: f32 = 1e-3;
+
+    pub fn new(eye: Vec3, target: Vec3, projection: Projection, width: f32, height: f32) -> Self {
+        Camera {
+            eye,
+            target,
+            up: Vec3::Y,
+            projection,
+            width,
+            height,
+        }
+    }
+
+    // circle the camera around its target: `yaw` around the up axis, `pitch` above the horizon
+    pub fn orbit(&mut self, distance: f32, yaw: f32, pitch: f32) -> &mut Self {
+        let (right, up, forward) = basis(Vec3::Z, self.up);
+        let offset = (right * yaw.sin() * pitch.cos() + up * pitch.sin()
+            - forward * yaw.cos() * pitch.cos())
+            * distance;
+        self.eye = self.target + offset;
+        self
+    }
+
+    // None for points behind the camera in perspective
+    pub fn project(&self, point: Vec3) -> Option<Projected> {
+        let (right, up, forward) = basis(self.target - self.eye, self.up);
+        let relative = point - self.eye;
+        let (x, y, depth) = (relative.dot(right), relative.dot(up), relative.dot(forward));
+
+        let scale = match self.projection {
+            Projection::Orthographic { scale } => scale,
+            Projection::Perspective { fov } => {
+                if depth <= Self::NEAR {
+                    return None;
+                }
+                self.height / 2.0 / (fov / 2.0).tan() / depth
+            }
+        };
+
+        Some(Projected {
+            point: Vec2::new(self.width / 2.0 + x * scale, self.height / 2.0 - y * scale),
+            depth,
+        })
+    }
+
+    // project a 3D polyline, split where it passes behind the camera
+    pub fn project_polyline(&self, points: &[Vec3]) -> Vec<Vec<Projected>> {
+        let mut parts = vec![Vec::new()];
+        for point in points {
+            match self.project(*point) {
+                Some(p) => parts.last_mut().unwrap().push(p),
+                None if !parts.last().unwrap().is_empty() => parts.push(Vec::new()),
+                None => {}
+            }
+        }
+
+        parts.retain(|part| part.len() > 1);
+        parts
+    }
+}
+
+// line weight for a depth: `weights[0]` at `near` thinning to `weights[1]` at `far`
+pub fn depth_weight(depth: f32, near: f32, far: f32, weights: [f32; 2]) -> f32 {
+    let t = ((depth - near) / (far - near).max(f32::EPSILON)).clamp(0.0, 1.0);
+    weights[0] + (weights[1] - weights[0]) * t
+}
+
+// right, up and forward unit vectors of a view looking along `forward`,
+// or along +z when there is no direction to look along (eye on the target)
+fn basis(forward: Vec3, up: Vec3) -> (Vec3, Vec3, Vec3) {
+    let mut forward = forward.normalize_or_zero();
+    if forward == Vec3::ZERO {
+        forward = Vec3::Z;
+    }
+    let mut right = forward.cross(up).normalize_or_zero();
+    if right == Vec3::ZERO {
+        right = forward.any_orthonormal_vector();
+    }
+    (right, right.cross(forward), forward)
+}
@@ -0,0 +1,351 @@
+use crate::common::Seedable;
+use crate::spatial::hash::SpatialHash;
+use crate::svg::{self, Polyline};
+use crate::utils::rng;
+use nannou::glam::Vec2;
+use nannou::prelude::TAU;
+use nannou::rand::rngs::StdRng;
+use nannou::rand::Rng;
+use std::path::Path;
+
+// Differential growth: a polyline whose nodes push away from every node nearby and are
+// held to their neighbours along the line. Edges that stretch past `max_edge` get a node
+// inserted in their middle, so the line keeps growing and folds into coral-like curves.
+//
+// Every step moves each node by
+//
+// attraction : towards its neighbours along the line, once they are further than
+//              half of `max_edge` (a fraction of the distance per step)
+// repulsion  : away from the other nodes within `radius`, up to `repulsion` world units
+//              each, harder the closer they are
+// alignment  : towards the midpoint of its two neighbours, smoothing the line
+//
+// limited to `max_speed`. Inserted nodes are nudged a little at random, from the seed,
+// and `growth` adds extra nodes at random edges to break the symmetry of regular shapes.
+//
+//     let mut growth = Growth::circle(center, 50.0, 20, 5.0);
+//     growth.repulsion(0.5, 10.0).set_seed(seed);
+//     for _ in 0..500 {
+//         growth.step();
+//     }
+//     growth.save_svg("growth.svg", center - 400.0, center + 400.0)?;
+pub struct Growth {
+    nodes: Vec<Vec2>,
+    closed: bool,
+    max_edge: f32,
+    attraction: f32,
+    repulsion: f32,
+    radius: f32,
+    alignment: f32,
+    max_speed: f32,
+    growth: f32,
+    max_nodes: usize,
+    bounds: Option<[Vec2; 2]>,
+    iteration: usize,
+    hash: SpatialHash,
+    rng: StdRng,
+    seed: u32,
+}
+
+impl Seedable for Growth {
+    fn set_seed(&mut self, seed: u32) -> &mut Self {
+        self.seed = seed;
+        self.rng = rng(seed as u64);
+        self
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+impl Growth {
+    pub const DEFAULT_SEED: u32 = 0;
+    pub const DEFAULT_MAX_NODES: usize = 10_000;
+
+    // nodes : the starting line, closed joins its last node back to the first
+    // max_edge : edges longer than this are split, sets the scale of the pattern
+    pub fn new(nodes: Vec<Vec2>, closed: bool, max_edge: f32) -> Self {
+        let max_edge = max_edge.max(f32::EPSILON);
+        Growth {
+            nodes,
+            closed,
+            max_edge,
+            attraction: 0.2,
+            repulsion: 0.5,
+            radius: max_edge * 2.0,
+            alignment: 0.4,
+            max_speed: max_edge / 2.0,
+            growth: 0.0,
+            max_nodes: Self::DEFAULT_MAX_NODES,
+            bounds: None,
+            iteration: 0,
+            hash: SpatialHash::new(max_edge * 2.0),
+            rng: rng(Self::DEFAULT_SEED as u64),
+            seed: Self::DEFAULT_SEED,
+        }
+    }
+
+    // a closed circle of `count` nodes
+    pub fn circle(center: Vec2, radius: f32, count: usize, max_edge: f32) -> Self {
+        let count = count.max(3);
+        let nodes = (0..count)
+            .map(|n| {
+                let angle = n as f32 / count as f32 * TAU;
+                center + Vec2::new(angle.cos(), angle.sin()) * radius
+            })
+            .collect();
+        Growth::new(nodes, true, max_edge)
+    }
+
+    pub fn attraction(&mut self, attraction: f32) -> &mut Self {
+        self.attraction = attraction.max(0.0);
+        self
+    }
+
+    // strength and radius of the push between nodes, the radius sets how far folds stay apart
+    pub fn repulsion(&mut self, repulsion: f32, radius: f32) -> &mut Self {
+        self.repulsion = repulsion.max(0.0);
+        self.radius = radius.max(f32::EPSILON);
+        self.hash = SpatialHash::new(self.radius);
+        self
+    }
+
+    pub fn alignment(&mut self, alignment: f32) -> &mut Self {
+        self.alignment = alignment.max(0.0);
+        self
+    }
+
+    // largest distance a node moves per step
+    pub fn max_speed(&mut self, speed: f32) -> &mut Self {
+        self.max_speed = speed.max(0.0);
+        self
+    }
+
+    // nodes inserted at random edges per step, e.g. 0.5 for one every other step
+    pub fn growth(&mut self, rate: f32) -> &mut Self {
+        self.growth = rate.max(0.0);
+        self
+    }
+
+    // no more nodes are inserted past this, the line keeps relaxing
+    pub fn max_nodes(&mut self, count: usize) -> &mut Self {
+        self.max_nodes = count;
+        self
+    }
+
+    // keeps the nodes inside a rectangle
+    pub fn bounds(&mut self, min: Vec2, max: Vec2) -> &mut Self {
+        self.bounds = Some([min.min(max), min.max(max)]);
+        self
+    }
+
+    pub fn nodes(&self) -> &[Vec2] {
+        &self.nodes
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    // steps taken so far
+    pub fn iteration(&self) -> usize {
+        self.iteration
+    }
+
+    // the curve as it is now, closed curves repeat their first node at the end
+    pub fn polyline(&self) -> Polyline {
+        let mut line = self.nodes.clone();
+        if self.closed && !line.is_empty() {
+            line.push(line[0]);
+        }
+        line
+    }
+
+    // the line as an svg path, showing the world rectangle between `min` and `max`
+    pub fn save_svg(&self, path: impl AsRef<Path>, min: Vec2, max: Vec2) -> std::io::Result<()> {
+        svg::save_paths_in(
+            path,
+            min,
+            max,
+            std::slice::from_ref(&self.nodes),
+            self.closed,
+        )
+    }
+
+    pub fn step(&mut self) -> &mut Self {
+        let n = self.nodes.len();
+        if n < 2 {
+            return self;
+        }
+
+        self.hash.clear();
+        self.nodes.iter().for_each(|p| {
+            self.hash.insert(*p);
+        });
+
+        // all moves from the same snapshot of the nodes, so the order does not matter
+        let rest = self.max_edge / 2.0;
+        let moves: Vec<Vec2> = (0..n)
+            .map(|k| {
+                let p = self.nodes[k];
+                let (prev, next) = self.neighbors(k);
+                let mut delta = Vec2::ZERO;
+
+                for neighbor in [prev, next].into_iter().flatten() {
+                    let offset = self.nodes[neighbor] - p;
+                    if offset.length() > rest {
+                        delta += offset * self.attraction;
+                    }
+                }
+
+                // neighbours along the line are left to the attraction, or every edge
+                // would be pushed past `max_edge` and split on every step
+                let mut push = Vec2::ZERO;
+                self.hash.for_each_within(p, self.radius, |other, q| {
+                    let offset = p - q;
+                    let d = offset.length();
+                    let linked = other == k || Some(other) == prev || Some(other) == next;
+                    if !linked && d > f32::EPSILON {
+                        push += offset / d * (1.0 - d / self.radius);
+                    }
+                });
+                delta += push * self.repulsion;
+
+                if let (Some(prev), Some(next)) = (prev, next) {
+                    let mid = (self.nodes[prev] + self.nodes[next]) / 2.0;
+                    delta += (mid - p) * self.alignment;
+                }
+
+                delta.clamp_length_max(self.max_speed)
+            })
+            .collect();
+
+        for (node, delta) in self.nodes.iter_mut().zip(moves) {
+            *node += delta;
+            if let Some([min, max]) = self.bounds {
+                *node = node.clamp(min, max);
+            }
+        }
+
+        self.split_edges();
+        self.grow();
+        self.iteration += 1;
+        self
+    }
+
+    // indices of the nodes before and after k along the line, None past the ends of open lines
+    fn neighbors(&self, k: usize) -> (Option<usize>, Option<usize>) {
+        let n = self.nodes.len();
+        if self.closed {
+            (Some((k + n - 1) % n), Some((k + 1) % n))
+        } else {
+            ((k > 0).then(|| k - 1), (k + 1 < n).then_some(k + 1))
+        }
+    }
+
+    // a node in the middle of every edge longer than `max_edge`
+    fn split_edges(&mut self) {
+        let edges = if self.closed {
+            self.nodes.len()
+        } else {
+            self.nodes.len() - 1
+        };
+        let mut nodes = Vec::with_capacity(self.nodes.len() * 2);
+        for k in 0..self.nodes.len() {
+            let a = self.nodes[k];
+            nodes.push(a);
+            if k < edges && nodes.len() + self.nodes.len() - k <= self.max_nodes {
+                let b = self.nodes[(k + 1) % self.nodes.len()];
+                if a.distance(b) > self.max_edge {
+                    nodes.push(self.midpoint(a, b));
+                }
+            }
+        }
+        self.nodes = nodes;
+    }
+
+    // `growth` extra nodes on average, at random edges
+    fn grow(&mut self) {
+        let mut count = self.growth.floor() as usize;
+        if self.rng.gen::<f32>() < self.growth.fract() {
+            count += 1;
+        }
+        for _ in 0..count {
+            let n = self.nodes.len();
+            let edges = if self.closed { n } else { n - 1 };
+            if edges == 0 || n >= self.max_nodes {
+                return;
+            }
+            let k = self.rng.gen_range(0..edges);
+            let (a, b) = (self.nodes[k], self.nodes[(k + 1) % n]);
+            let mid = self.midpoint(a, b);
+            self.nodes.insert(k + 1, mid);
+        }
+    }
+
+    // between a and b, nudged off the edge by up to a tenth of its length
+    fn midpoint(&mut self, a: Vec2, b: Vec2) -> Vec2 {
+        let normal = (b - a).perp();
+        (a + b) / 2.0 + normal * (self.rng.gen::<f32>() - 0.5) * 0.2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grown(seed: u32, steps: usize) -> Growth {
+        let mut growth = Growth::circle(Vec2::ZERO, 20.0, 12, 5.0);
+        growth.growth(0.5).set_seed(seed);
+        for _ in 0..steps {
+            growth.step();
+        }
+        growth
+    }
+
+    #[test]
+    fn long_edges_are_split() {
+        // 12 nodes on a circle of radius 20 are about 10.4 apart, past max_edge
+        let mut growth = Growth::circle(Vec2::ZERO, 20.0, 12, 5.0);
+        growth.step();
+        assert!(growth.nodes().len() >= 24);
+        assert_eq!(growth.iteration(), 1);
+        assert_eq!(growth.polyline().first(), growth.polyline().last());
+    }
+
+    #[test]
+    fn growth_is_reproducible_for_a_seed() {
+        let a = grown(3, 60);
+        assert_eq!(a.nodes(), grown(3, 60).nodes());
+        assert_ne!(a.nodes(), grown(4, 60).nodes());
+        assert!(a.nodes().len() > 40);
+    }
+
+    #[test]
+    fn max_nodes_and_bounds_are_kept() {
+        let mut growth = Growth::circle(Vec2::ZERO, 20.0, 12, 5.0);
+        growth
+            .growth(2.0)
+            .max_nodes(80)
+            .bounds(Vec2::splat(-25.0), Vec2::splat(25.0));
+        for _ in 0..200 {
+            growth.step();
+        }
+        assert!(growth.nodes().len() <= 80);
+        assert!(growth.nodes().iter().all(|p| p.abs().max_element() <= 25.0));
+    }
+
+    #[test]
+    fn svg_view_box_follows_the_given_rectangle() {
+        let path = std::env::temp_dir().join(format!("lib-growth-{}.svg", std::process::id()));
+        let growth = Growth::circle(Vec2::ZERO, 20.0, 12, 5.0);
+        growth
+            .save_svg(&path, Vec2::splat(-30.0), Vec2::splat(30.0))
+            .unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(svg.contains("viewBox=\"-30 -30 60 60\""));
+        assert_eq!(svg.matches("<path").count(), 1);
+    }
+}
@@ -1,4 +1,11 @@
+pub mod canvas;
 pub mod colors;
 pub mod common;
 pub mod forces;
+pub mod growth;
+pub mod packing;
+pub mod particles;
+pub mod spatial;
+pub mod svg;
 pub mod utils;
+pub mod verlet;
@@ -0,0 +1,431 @@
+use crate::common::Seedable;
+use crate::forces::scalar::ScalarSource;
+use crate::spatial::hash::SpatialHash;
+use crate::utils::{exp_rng, rng};
+use nannou::glam::Vec2;
+use nannou::prelude::TAU;
+use nannou::rand::rngs::StdRng;
+use nannou::rand::Rng;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PackedCircle {
+    pub center: Vec2,
+    pub radius: f32,
+}
+
+// How radii are picked for new circles
+//
+// Fixed   : always the same radius
+// Uniform : anywhere in [min, max]
+// Exp     : `exp_rng`, many small circles and a few large ones (or the other way round)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Radii {
+    Fixed(f32),
+    Uniform(f32, f32),
+    Exp {
+        k: f32,
+        min: f32,
+        max: f32,
+        favor_min: bool,
+    },
+}
+
+impl Radii {
+    pub fn sample(&self, rng: &mut StdRng) -> f32 {
+        match *self {
+            Radii::Fixed(r) => r,
+            Radii::Uniform(min, max) => min + (max - min) * rng.gen::<f32>(),
+            Radii::Exp {
+                k,
+                min,
+                max,
+                favor_min,
+            } => exp_rng(rng, k, min, max, favor_min).clamp(min, max),
+        }
+    }
+
+    pub fn max(&self) -> f32 {
+        match *self {
+            Radii::Fixed(r) => r,
+            Radii::Uniform(min, max) => min.max(max),
+            Radii::Exp { min, max, .. } => min.max(max),
+        }
+    }
+}
+
+// Non-overlapping circles inside a rectangle, optionally restricted to a mask
+// (any `ScalarSource` read at world coordinates, inside where it is at least 0.5),
+// with at least `gap` between neighbouring circles. The masks in `forces::mask` are
+// read in field indices instead, scale them to world coordinates in a closure:
+//
+//     packer.mask(move |x: f64, y: f64| mask.get((x - x0) / res, (y - y0) / res));
+//
+// grow       : random centers, each circle grows until it touches a neighbour, the bounds
+//              or the mask edge, or reaches the radius drawn from `radii`. Later circles
+//              find less room, so the gaps fill up with smaller and smaller ones.
+// front_chain: circles with the drawn radii placed tangent to each other outwards from
+//              the center (Wang et al., 2006), a dense pack without holes.
+//
+//     let mut packer = Packer::new(min, max, Radii::Exp { k: 20.0, min: 2.0, max: 64.0, favor_min: true });
+//     let circles = packer.gap(2.0).set_seed(seed).grow(500);
+pub struct Packer {
+    min: Vec2,
+    max: Vec2,
+    radii: Radii,
+    min_radius: f32,
+    gap: f32,
+    attempts: usize,
+    mask: Option<Box<dyn ScalarSource>>,
+    rng: StdRng,
+    seed: u32,
+}
+
+impl Seedable for Packer {
+    fn set_seed(&mut self, seed: u32) -> &mut Self {
+        self.seed = seed;
+        self.rng = rng(seed as u64);
+        self
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+impl Packer {
+    pub const DEFAULT_SEED: u32 = 0;
+    pub const DEFAULT_ATTEMPTS: usize = 1000;
+    // points on the circumference checked against the mask
+    const MASK_SAMPLES: usize = 16;
+
+    pub fn new(min: Vec2, max: Vec2, radii: Radii) -> Self {
+        Packer {
+            min: min.min(max),
+            max: min.max(max),
+            radii,
+            min_radius: 1.0,
+            gap: 0.0,
+            attempts: Self::DEFAULT_ATTEMPTS,
+            mask: None,
+            rng: rng(Self::DEFAULT_SEED as u64),
+            seed: Self::DEFAULT_SEED,
+        }
+    }
+
+    // space kept between any two circles
+    pub fn gap(&mut self, gap: f32) -> &mut Self {
+        self.gap = gap.max(0.0);
+        self
+    }
+
+    // circles that cannot grow this large are not placed, see `grow`
+    pub fn min_radius(&mut self, radius: f32) -> &mut Self {
+        self.min_radius = radius.max(f32::EPSILON);
+        self
+    }
+
+    // failed tries in a row after which `grow` gives up, the space is considered full
+    pub fn attempts(&mut self, attempts: usize) -> &mut Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    // read at world coordinates, see the note on field index masks above
+    pub fn mask(&mut self, mask: impl ScalarSource + 'static) -> &mut Self {
+        self.mask = Some(Box::new(mask));
+        self
+    }
+
+    // whether the whole circle is within the bounds and the mask
+    pub fn fits(&self, center: Vec2, radius: f32) -> bool {
+        let r = Vec2::splat(radius);
+        if (center - r).cmplt(self.min).any() || (center + r).cmpgt(self.max).any() {
+            return false;
+        }
+        match &self.mask {
+            Some(mask) => {
+                let inside = |p: Vec2| mask.get(p.x as f64, p.y as f64) >= 0.5;
+                inside(center)
+                    && (0..Self::MASK_SAMPLES).all(|n| {
+                        let angle = n as f32 / Self::MASK_SAMPLES as f32 * TAU;
+                        inside(center + Vec2::new(angle.cos(), angle.sin()) * radius)
+                    })
+            }
+            None => true,
+        }
+    }
+
+    // up to `count` circles grown at random free spots
+    pub fn grow(&mut self, count: usize) -> Vec<PackedCircle> {
+        let extent = (self.max - self.min).max_element();
+        let mut classes = Classes::new(self.min_radius + self.gap, extent);
+        let mut circles: Vec<PackedCircle> = Vec::new();
+        let mut failures = 0;
+
+        while circles.len() < count && failures < self.attempts {
+            let center =
+                self.min + (self.max - self.min) * Vec2::new(self.rng.gen(), self.rng.gen());
+            let target = self.radii.sample(&mut self.rng);
+
+            // room left before touching the nearest circle or the bounds
+            let bounds = (center - self.min).min(self.max - center).min_element();
+            let mut radius = classes.room(&circles, center, target.min(bounds), self.gap);
+
+            while radius >= self.min_radius && !self.fits(center, radius) {
+                radius *= 0.9;
+            }
+            if radius < self.min_radius {
+                failures += 1;
+                continue;
+            }
+
+            failures = 0;
+            classes.insert(center, radius + self.gap, circles.len());
+            circles.push(PackedCircle { center, radius });
+        }
+
+        circles
+    }
+
+    // `count` circles packed tangentially outwards from the center of the bounds,
+    // keeping the ones that fit in the bounds and the mask
+    pub fn front_chain(&mut self, count: usize) -> Vec<PackedCircle> {
+        let half = self.gap / 2.0;
+        let radii: Vec<f32> = (0..count)
+            .map(|_| self.radii.sample(&mut self.rng).max(self.min_radius) + half)
+            .collect();
+        let center = (self.min + self.max) / 2.0;
+
+        pack(&radii)
+            .into_iter()
+            .map(|c| PackedCircle {
+                center: c.center + center,
+                radius: c.radius - half,
+            })
+            .filter(|c| self.fits(c.center, c.radius))
+            .collect()
+    }
+}
+
+// Front-chain packing around the origin, after d3-hierarchy's `packSiblings`: every new
+// circle goes tangent to the pair on the chain closest to the origin; if it overlaps
+// another chain circle, the chain is cut short to that circle and the placement retried.
+fn pack(radii: &[f32]) -> Vec<PackedCircle> {
+    let mut circles: Vec<PackedCircle> = radii
+        .iter()
+        .map(|r| PackedCircle {
+            center: Vec2::ZERO,
+            radius: *r,
+        })
+        .collect();
+    let n = circles.len();
+    if n < 2 {
+        return circles;
+    }
+
+    let r = (circles[0].radius, circles[1].radius);
+    circles[0].center.x = -r.1;
+    circles[1].center.x = r.0;
+    if n == 2 {
+        return circles;
+    }
+    circles[2] = place(circles[1], circles[0], circles[2].radius);
+
+    // the front chain, as a circular doubly linked list over the circle indices
+    let mut next = vec![0; n];
+    let mut prev = vec![0; n];
+    let (mut a, mut b) = (0, 1);
+    next[0] = 1;
+    prev[1] = 0;
+    next[1] = 2;
+    prev[2] = 1;
+    next[2] = 0;
+    prev[0] = 2;
+
+    let score = |circles: &[PackedCircle], next: &[usize], node: usize| {
+        let (a, b) = (circles[node], circles[next[node]]);
+        let ab = a.radius + b.radius;
+        ((a.center * b.radius + b.center * a.radius) / ab).length_squared()
+    };
+
+    let mut i = 3;
+    'pack: while i < n {
+        circles[i] = place(circles[a], circles[b], circles[i].radius);
+        let c = circles[i];
+
+        // walk the chain from both ends of the pair, always on the shorter side
+        let (mut j, mut k) = (next[b], prev[a]);
+        let (mut sj, mut sk) = (circles[b].radius, circles[a].radius);
+        loop {
+            if sj <= sk {
+                if intersects(circles[j], c) {
+                    b = j;
+                    next[a] = b;
+                    prev[b] = a;
+                    continue 'pack;
+                }
+                sj += circles[j].radius;
+                j = next[j];
+            } else {
+                if intersects(circles[k], c) {
+                    a = k;
+                    next[a] = b;
+                    prev[b] = a;
+                    continue 'pack;
+                }
+                sk += circles[k].radius;
+                k = prev[k];
+            }
+            if j == next[k] {
+                break;
+            }
+        }
+
+        // insert between a and b, then move to the pair closest to the origin
+        prev[i] = a;
+        next[i] = b;
+        next[a] = i;
+        prev[b] = i;
+        b = i;
+
+        let mut best = score(&circles, &next, a);
+        let mut node = next[b];
+        while node != b {
+            let s = score(&circles, &next, node);
+            if s < best {
+                a = node;
+                best = s;
+            }
+            node = next[node];
+        }
+        b = next[a];
+        i += 1;
+    }
+
+    circles
+}
+
+// a circle of radius r tangent to both a and b, on the outer side of the chain
+fn place(b: PackedCircle, a: PackedCircle, r: f32) -> PackedCircle {
+    let d = b.center - a.center;
+    let d2 = d.length_squared();
+    let center = if d2 > 0.0 {
+        let a2 = (a.radius + r).powi(2);
+        let b2 = (b.radius + r).powi(2);
+        if a2 > b2 {
+            let x = (d2 + b2 - a2) / (2.0 * d2);
+            let y = (b2 / d2 - x * x).max(0.0).sqrt();
+            b.center - x * d + y * Vec2::new(-d.y, d.x)
+        } else {
+            let x = (d2 + a2 - b2) / (2.0 * d2);
+            let y = (a2 / d2 - x * x).max(0.0).sqrt();
+            a.center + x * d + y * Vec2::new(-d.y, d.x)
+        }
+    } else {
+        a.center + Vec2::new(r, 0.0)
+    };
+    PackedCircle { center, radius: r }
+}
+
+// overlapping by more than a rounding error
+fn intersects(a: PackedCircle, b: PackedCircle) -> bool {
+    let dr = a.radius + b.radius - 1e-4;
+    dr > 0.0 && dr * dr > a.center.distance_squared(b.center)
+}
+
+// circles by size class, each class in its own `SpatialHash` with cells four times as wide
+// as the reach (radius plus gap) of its largest circles, so a candidate only searches as far
+// as the circles of each class can reach and a few large circles do not widen the search
+// through the small ones
+struct Classes {
+    base: f32,
+    // (hash of the centers, circle index of every hash index), class k reaching up to base * 2^k
+    classes: Vec<(SpatialHash, Vec<usize>)>,
+}
+
+impl Classes {
+    // classes below 1/1024 of the bounds would only add hash cells to scan, so the smallest
+    // one is floored there and any circle that fits in the bounds lands in the first 11
+    fn new(base: f32, extent: f32) -> Self {
+        Classes {
+            base: base.max(extent / 1024.0).max(f32::EPSILON),
+            classes: Vec::new(),
+        }
+    }
+
+    fn reach(&self, class: usize) -> f32 {
+        self.base * 2f32.powi(class as i32)
+    }
+
+    // a circle reaching `reach` (its radius plus the gap) from `center`
+    fn insert(&mut self, center: Vec2, reach: f32, index: usize) {
+        let class = (reach / self.base).log2().ceil().max(0.0) as usize;
+        while self.classes.len() <= class {
+            let size = 4.0 * self.reach(self.classes.len());
+            self.classes.push((SpatialHash::new(size), Vec::new()));
+        }
+        let (hash, indices) = &mut self.classes[class];
+        hash.insert(center);
+        indices.push(index);
+    }
+
+    // `radius` shrunk until a circle there keeps clear of every inserted one
+    fn room(&self, circles: &[PackedCircle], center: Vec2, mut radius: f32, gap: f32) -> f32 {
+        for (class, (hash, indices)) in self.classes.iter().enumerate().rev() {
+            hash.for_each_within(center, radius + self.reach(class), |n, q| {
+                radius = radius.min(center.distance(q) - circles[indices[n]].radius - gap);
+            });
+        }
+        radius
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_packed(circles: &[PackedCircle], min: Vec2, max: Vec2, gap: f32) {
+        for (n, a) in circles.iter().enumerate() {
+            let r = Vec2::splat(a.radius - 1e-3);
+            assert!((a.center - r).cmpge(min).all() && (a.center + r).cmple(max).all());
+            for b in &circles[n + 1..] {
+                let clearance = a.center.distance(b.center) - a.radius - b.radius;
+                assert!(clearance >= gap - 1e-3, "{:?} {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn grown_circles_keep_their_gap_inside_the_bounds() {
+        let (min, max) = (Vec2::new(-50.0, 0.0), Vec2::new(250.0, 120.0));
+        let radii = Radii::Exp {
+            k: 10.0,
+            min: 1.0,
+            max: 40.0,
+            favor_min: true,
+        };
+        let circles = Packer::new(min, max, radii).gap(1.5).set_seed(3).grow(400);
+        assert!(circles.len() > 100);
+        assert_packed(&circles, min, max, 1.5);
+    }
+
+    #[test]
+    fn tiny_min_radius_terminates() {
+        let (min, max) = (Vec2::ZERO, Vec2::splat(1000.0));
+        let circles = Packer::new(min, max, Radii::Uniform(0.0, 30.0))
+            .min_radius(0.0)
+            .grow(300);
+        assert_eq!(circles.len(), 300);
+        assert_packed(&circles, min, max, 0.0);
+    }
+
+    #[test]
+    fn front_chain_circles_do_not_overlap() {
+        let (min, max) = (Vec2::ZERO, Vec2::splat(200.0));
+        let circles = Packer::new(min, max, Radii::Uniform(2.0, 10.0))
+            .gap(1.0)
+            .front_chain(200);
+        assert!(!circles.is_empty());
+        assert_packed(&circles, min, max, 1.0);
+    }
+}
@@ -0,0 +1,400 @@
+use crate::common::Seedable;
+use crate::particles::particle::Particle;
+use crate::particles::system::ParticleSystem;
+use crate::utils::rng;
+use nannou::glam::Vec2;
+use nannou::image::DynamicImage;
+use nannou::prelude::TAU;
+use nannou::rand::rngs::StdRng;
+use nannou::rand::Rng;
+
+// Where an emitter puts new particles, in world coordinates.
+//
+// point   : all at the same spot, e.g. a burst
+// line    : uniformly along a segment
+// rect    : uniformly inside a rectangle
+// circle  : uniformly inside a disc
+// polygon : uniformly inside a simple closed polygon, in either winding
+// path    : uniformly along a polyline, by length
+// image   : more particles where the image is darker, stretched over a rectangle
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shape(Kind);
+
+#[derive(Clone, Debug, PartialEq)]
+enum Kind {
+    Point(Vec2),
+    Line(Vec2, Vec2),
+    Rect(Vec2, Vec2),
+    Circle(Vec2, f32),
+    // triangles with the cumulative area up to each of them
+    Polygon(Vec<[Vec2; 3]>, Vec<f32>),
+    // points with the cumulative length up to each of them
+    Path(Vec<Vec2>, Vec<f32>),
+    // cumulative pixel weights, row-major
+    Image {
+        cdf: Vec<f32>,
+        width: usize,
+        height: usize,
+        min: Vec2,
+        max: Vec2,
+    },
+}
+
+impl Shape {
+    pub fn point(point: Vec2) -> Self {
+        Shape(Kind::Point(point))
+    }
+
+    pub fn line(from: Vec2, to: Vec2) -> Self {
+        Shape(Kind::Line(from, to))
+    }
+
+    pub fn rect(min: Vec2, max: Vec2) -> Self {
+        Shape(Kind::Rect(min.min(max), min.max(max)))
+    }
+
+    pub fn circle(center: Vec2, radius: f32) -> Self {
+        Shape(Kind::Circle(center, radius.abs()))
+    }
+
+    pub fn polygon(points: Vec<Vec2>) -> Self {
+        let triangles = triangulate(&points);
+        let mut total = 0.0;
+        let areas = triangles
+            .iter()
+            .map(|[a, b, c]| {
+                total += (*b - *a).perp_dot(*c - *a).abs() / 2.0;
+                total
+            })
+            .collect();
+        Shape(Kind::Polygon(triangles, areas))
+    }
+
+    pub fn path(points: Vec<Vec2>) -> Self {
+        let mut lengths = Vec::with_capacity(points.len());
+        let mut total = 0.0;
+        for (n, point) in points.iter().enumerate() {
+            if n > 0 {
+                total += point.distance(points[n - 1]);
+            }
+            lengths.push(total);
+        }
+        Shape(Kind::Path(points, lengths))
+    }
+
+    // density from the luminance: black pixels emit, white ones never do;
+    // `invert` flips that so bright areas emit instead
+    pub fn image(image: &DynamicImage, min: Vec2, max: Vec2, invert: bool) -> Self {
+        let luma = image.to_luma8();
+        let (width, height) = (luma.width() as usize, luma.height() as usize);
+
+        let mut total = 0.0;
+        let cdf = luma
+            .pixels()
+            .map(|p| {
+                let v = p[0] as f32 / 255.0;
+                total += if invert { v } else { 1.0 - v };
+                total
+            })
+            .collect();
+
+        Shape(Kind::Image {
+            cdf,
+            width,
+            height,
+            min: min.min(max),
+            max: min.max(max),
+        })
+    }
+
+    pub fn sample(&self, rng: &mut StdRng) -> Vec2 {
+        match &self.0 {
+            Kind::Point(p) => *p,
+            Kind::Line(a, b) => a.lerp(*b, rng.gen::<f32>()),
+            Kind::Rect(min, max) => *min + (*max - *min) * Vec2::new(rng.gen(), rng.gen()),
+            Kind::Circle(center, radius) => {
+                let (angle, r) = (rng.gen::<f32>() * TAU, radius * rng.gen::<f32>().sqrt());
+                *center + Vec2::new(angle.cos(), angle.sin()) * r
+            }
+            Kind::Polygon(triangles, areas) => {
+                let total = areas.last().copied().unwrap_or(0.0);
+                if total <= 0.0 {
+                    return triangles.first().map_or(Vec2::ZERO, |t| t[0]);
+                }
+                let at = rng.gen::<f32>() * total;
+                let n = areas.partition_point(|a| *a <= at).min(areas.len() - 1);
+                let [a, b, c] = triangles[n];
+
+                // a point in the parallelogram on ab and ac, folded back into the triangle
+                let (mut u, mut v) = (rng.gen::<f32>(), rng.gen::<f32>());
+                if u + v > 1.0 {
+                    (u, v) = (1.0 - u, 1.0 - v);
+                }
+                a + (b - a) * u + (c - a) * v
+            }
+            Kind::Path(points, lengths) => {
+                let total = lengths.last().copied().unwrap_or(0.0);
+                if points.len() < 2 || total <= 0.0 {
+                    return points.first().copied().unwrap_or(Vec2::ZERO);
+                }
+                let at = rng.gen::<f32>() * total;
+                let n = lengths
+                    .partition_point(|l| *l < at)
+                    .clamp(1, points.len() - 1);
+                let t = (at - lengths[n - 1]) / (lengths[n] - lengths[n - 1]).max(f32::EPSILON);
+                points[n - 1].lerp(points[n], t)
+            }
+            Kind::Image {
+                cdf,
+                width,
+                height,
+                min,
+                max,
+            } => {
+                let total = cdf.last().copied().unwrap_or(0.0);
+                if total <= 0.0 {
+                    return *min + (*max - *min) * Vec2::new(rng.gen(), rng.gen());
+                }
+                let at = rng.gen::<f32>() * total;
+                let n = cdf.partition_point(|c| *c <= at).min(cdf.len() - 1);
+                let pixel = Vec2::new((n % width) as f32, (n / width) as f32);
+                let uv = (pixel + Vec2::new(rng.gen(), rng.gen()))
+                    / Vec2::new(*width as f32, *height as f32);
+                *min + (*max - *min) * uv
+            }
+        }
+    }
+}
+
+// Creates particles on a shape at a steady `rate` (per second) or in bursts, with initial
+// velocities `angle` ± `spread` / 2 (radians, 0 is +x) at a speed in [min, max].
+// Randomness comes from the emitter's own seed, so a scene replays the same every time.
+//
+//     let mut fountain = Emitter::new(Shape::line(a, b), 200.0);
+//     fountain.velocity(-PI / 2.0, 0.3, 50.0, 80.0).lifetime(2.0, 4.0).set_seed(seed);
+//     fountain.emit(dt, &mut system);
+//
+// An emitter can also feed a system's respawn: `system.respawn(move |_| emitter.particle())`.
+pub struct Emitter {
+    pub shape: Shape,
+    pub rate: f32,
+    angle: f32,
+    spread: f32,
+    speed: [f32; 2],
+    lifetime: Option<[f32; 2]>,
+    mass: f32,
+    accumulator: f32,
+    rng: StdRng,
+    seed: u32,
+}
+
+impl Seedable for Emitter {
+    fn set_seed(&mut self, seed: u32) -> &mut Self {
+        self.seed = seed;
+        self.rng = rng(seed as u64);
+        self
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+impl Emitter {
+    pub const DEFAULT_SEED: u32 = 0;
+
+    pub fn new(shape: Shape, rate: f32) -> Self {
+        Emitter {
+            shape,
+            rate: rate.max(0.0),
+            angle: 0.0,
+            spread: TAU,
+            speed: [0.0, 0.0],
+            lifetime: None,
+            mass: 1.0,
+            accumulator: 0.0,
+            rng: rng(Self::DEFAULT_SEED as u64),
+            seed: Self::DEFAULT_SEED,
+        }
+    }
+
+    // a spread of TAU sends particles in every direction
+    pub fn velocity(
+        &mut self,
+        angle: f32,
+        spread: f32,
+        min_speed: f32,
+        max_speed: f32,
+    ) -> &mut Self {
+        self.angle = angle;
+        self.spread = spread.clamp(0.0, TAU);
+        self.speed = [min_speed.min(max_speed), min_speed.max(max_speed)];
+        self
+    }
+
+    // seconds, picked uniformly in [min, max] for every particle
+    pub fn lifetime(&mut self, min: f32, max: f32) -> &mut Self {
+        self.lifetime = Some([min.min(max), min.max(max)]);
+        self
+    }
+
+    pub fn mass(&mut self, mass: f32) -> &mut Self {
+        self.mass = mass.max(f32::EPSILON);
+        self
+    }
+
+    // a position on the shape
+    pub fn position(&mut self) -> Vec2 {
+        self.shape.sample(&mut self.rng)
+    }
+
+    pub fn particle<T: Default>(&mut self) -> Particle<T> {
+        self.particle_with(T::default())
+    }
+
+    pub fn particle_with<T>(&mut self, data: T) -> Particle<T> {
+        let mut particle = Particle::with_data(self.position(), data);
+
+        let angle = self.angle + (self.rng.gen::<f32>() - 0.5) * self.spread;
+        let speed = self.speed[0] + (self.speed[1] - self.speed[0]) * self.rng.gen::<f32>();
+        particle
+            .velocity(Vec2::new(angle.cos(), angle.sin()) * speed)
+            .mass(self.mass);
+        if let Some([min, max]) = self.lifetime {
+            particle.lifetime(min + (max - min) * self.rng.gen::<f32>());
+        }
+        particle
+    }
+
+    // `count` particles at once
+    pub fn burst<T: Default>(&mut self, count: usize, system: &mut ParticleSystem<T>) -> &mut Self {
+        self.burst_with(count, system, T::default)
+    }
+
+    // same as `burst`, with the data of every particle made by `data`
+    pub fn burst_with<T>(
+        &mut self,
+        count: usize,
+        system: &mut ParticleSystem<T>,
+        mut data: impl FnMut() -> T,
+    ) -> &mut Self {
+        for _ in 0..count {
+            let particle = self.particle_with(data());
+            system.spawn(particle);
+        }
+        self
+    }
+
+    // the particles due after `elapsed` seconds at the emitter's rate, returns how many
+    pub fn emit<T: Default>(&mut self, elapsed: f32, system: &mut ParticleSystem<T>) -> usize {
+        self.emit_with(elapsed, system, T::default)
+    }
+
+    // same as `emit`, with the data of every particle made by `data`
+    pub fn emit_with<T>(
+        &mut self,
+        elapsed: f32,
+        system: &mut ParticleSystem<T>,
+        data: impl FnMut() -> T,
+    ) -> usize {
+        self.accumulator += self.rate * elapsed.max(0.0);
+        let count = self.accumulator.floor() as usize;
+        self.accumulator -= count as f32;
+        self.burst_with(count, system, data);
+        count
+    }
+}
+
+// ear clipping: cuts off one convex corner with no other vertex inside at a time
+// polygons that cross themselves have no clean ears left at some point, the rest is fanned
+fn triangulate(points: &[Vec2]) -> Vec<[Vec2; 3]> {
+    let mut points = points;
+    if points.len() > 1 && points.first() == points.last() {
+        points = &points[..points.len() - 1];
+    }
+    let area: f32 = (0..points.len())
+        .map(|k| points[k].perp_dot(points[(k + 1) % points.len()]))
+        .sum();
+    let winding = area.signum();
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len().saturating_sub(2));
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let corner = |k: usize| {
+            (
+                points[remaining[(k + m - 1) % m]],
+                points[remaining[k]],
+                points[remaining[(k + 1) % m]],
+            )
+        };
+        let ear = (0..m).find(|k| {
+            let (a, b, c) = corner(*k);
+            (b - a).perp_dot(c - b) * winding > 0.0
+                && !remaining.iter().any(|n| {
+                    let p = points[*n];
+                    p != a && p != b && p != c && in_triangle(p, a, b, c)
+                })
+        });
+
+        match ear {
+            Some(k) => {
+                let (a, b, c) = corner(k);
+                triangles.push([a, b, c]);
+                remaining.remove(k);
+            }
+            None => {
+                let first = points[remaining[0]];
+                for pair in remaining[1..].windows(2) {
+                    triangles.push([first, points[pair[0]], points[pair[1]]]);
+                }
+                return triangles;
+            }
+        }
+    }
+
+    if let [a, b, c] = remaining[..] {
+        triangles.push([points[a], points[b], points[c]]);
+    }
+    triangles
+}
+
+// inside or on the edges of the triangle abc, in either winding
+fn in_triangle(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    let d = [
+        (b - a).perp_dot(p - a),
+        (c - b).perp_dot(p - b),
+        (a - c).perp_dot(p - c),
+    ];
+    d.iter().all(|d| *d >= 0.0) || d.iter().all(|d| *d <= 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emit_follows_the_rate_and_fills_data() {
+        let mut emitter = Emitter::new(Shape::rect(Vec2::ZERO, Vec2::new(10.0, 5.0)), 10.0);
+        let mut system = ParticleSystem::<usize>::new(0.01);
+
+        let mut next = 0;
+        let mut counter = || {
+            next += 1;
+            next
+        };
+        assert_eq!(emitter.emit_with(0.25, &mut system, &mut counter), 2);
+        assert_eq!(emitter.emit_with(0.25, &mut system, &mut counter), 3);
+        emitter.burst_with(4, &mut system, &mut counter);
+
+        let data: Vec<usize> = system.particles().iter().map(|p| p.data).collect();
+        assert_eq!(data, (1..=9).collect::<Vec<_>>());
+        for particle in system.particles() {
+            let p = particle.position;
+            assert!((0.0..=10.0).contains(&p.x) && (0.0..=5.0).contains(&p.y));
+        }
+
+        assert_eq!(emitter.emit(0.1, &mut system), 1);
+        assert_eq!(system.particles().last().unwrap().data, 0);
+    }
+}
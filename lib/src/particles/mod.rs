@@ -0,0 +1,4 @@
+pub mod emitter;
+pub mod particle;
+pub mod steering;
+pub mod system;
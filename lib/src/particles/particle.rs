@@ -0,0 +1,88 @@
+use nannou::glam::Vec2;
+
+// A point mass moved by a `ParticleSystem`, with anything else a sketch needs in `data`
+// (color, radius, a trail, ...).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Particle<T = ()> {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    // forces applied since the last step, divided by the mass; cleared every step
+    pub acceleration: Vec2,
+    pub mass: f32,
+    // seconds since the particle was spawned
+    pub age: f32,
+    // seconds the particle lives, forever if None
+    pub lifetime: Option<f32>,
+    pub data: T,
+}
+
+impl<T: Default> Particle<T> {
+    pub fn new(position: Vec2) -> Self {
+        Self::with_data(position, T::default())
+    }
+}
+
+impl<T> Particle<T> {
+    pub fn with_data(position: Vec2, data: T) -> Self {
+        Particle {
+            position,
+            velocity: Vec2::ZERO,
+            acceleration: Vec2::ZERO,
+            mass: 1.0,
+            age: 0.0,
+            lifetime: None,
+            data,
+        }
+    }
+
+    pub fn velocity(&mut self, velocity: Vec2) -> &mut Self {
+        self.velocity = velocity;
+        self
+    }
+
+    pub fn mass(&mut self, mass: f32) -> &mut Self {
+        self.mass = mass.max(f32::EPSILON);
+        self
+    }
+
+    pub fn lifetime(&mut self, seconds: f32) -> &mut Self {
+        self.lifetime = Some(seconds);
+        self
+    }
+
+    pub fn apply_force(&mut self, force: Vec2) -> &mut Self {
+        self.acceleration += force / self.mass;
+        self
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.lifetime.is_some_and(|lifetime| self.age >= lifetime)
+    }
+
+    // 0 when spawned, 1 at the end of its lifetime; 0 for immortal particles
+    pub fn life(&self) -> f32 {
+        match self.lifetime {
+            Some(lifetime) if lifetime > 0.0 => (self.age / lifetime).min(1.0),
+            Some(_) => 1.0,
+            None => 0.0,
+        }
+    }
+
+    // semi-implicit euler: velocity first, then position with the new velocity
+    pub fn step(&mut self, dt: f32) {
+        self.step_damped(dt, 1.0, None);
+    }
+
+    // like `step`, with the new velocity scaled by `damping` and limited to `max_speed`
+    // before it moves the particle
+    pub fn step_damped(&mut self, dt: f32, damping: f32, max_speed: Option<f32>) {
+        let mut velocity = (self.velocity + self.acceleration * dt) * damping;
+        if let Some(max) = max_speed {
+            velocity = velocity.clamp_length_max(max);
+        }
+        self.velocity = velocity;
+        self.position += velocity * dt;
+        self.acceleration = Vec2::ZERO;
+        self.age += dt;
+    }
+}
@@ -0,0 +1,353 @@
+use crate::common::Seedable;
+use crate::particles::particle::Particle;
+use crate::particles::system::ParticleForce;
+use crate::spatial::hash::SpatialHash;
+use crate::utils::{perlin, PerlinNoise};
+use nannou::glam::Vec2;
+use nannou::noise::NoiseFn;
+use nannou::prelude::PI;
+
+// Reynolds steering behaviors. Each one asks for a desired velocity, the steering force is
+// the difference to the current velocity, limited to the steering's `max_force`.
+//
+// Separation : away from neighbours closer than `radius`, closer ones push harder
+// Alignment  : along the average velocity of the neighbours within `radius`
+// Cohesion   : towards the average position of the neighbours within `radius`
+// Seek       : straight at the target
+// Flee       : straight away from the target, when closer than `radius`
+// Arrive     : at the target, slowing down within `slowing` of it
+// Wander     : a heading that drifts with noise, `scale` in world units and `rate` per second
+// Avoid      : around circles (center, radius) in the way, up to `look_ahead` ahead
+// Follow     : along the field given to `Steering::steer`, e.g. a `FlowField`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Behavior {
+    Separation {
+        radius: f32,
+    },
+    Alignment {
+        radius: f32,
+    },
+    Cohesion {
+        radius: f32,
+    },
+    Seek(Vec2),
+    Flee {
+        target: Vec2,
+        radius: f32,
+    },
+    Arrive {
+        target: Vec2,
+        slowing: f32,
+    },
+    Wander {
+        scale: f32,
+        rate: f32,
+    },
+    Avoid {
+        obstacles: Vec<(Vec2, f32)>,
+        look_ahead: f32,
+    },
+    Follow,
+}
+
+// Weighted behaviors applied as forces to particles, before every step of a system:
+//
+//     let mut flock = Steering::new(120.0, 300.0);
+//     flock
+//         .add(Behavior::Separation { radius: 12.0 }, 1.5)
+//         .add(Behavior::Alignment { radius: 30.0 }, 1.0)
+//         .add(Behavior::Cohesion { radius: 30.0 }, 1.0)
+//         .add(Behavior::Follow, 0.5);
+//     system.update_with(dt, &(), |boids, t| flock.steer(boids, t, &field));
+//
+// Neighbours are looked up in a spatial hash rebuilt on every call, and wandering is
+// driven by seeded noise, so a flock moves the same every run.
+pub struct Steering {
+    max_speed: f32,
+    max_force: f32,
+    behaviors: Vec<(Behavior, f32)>,
+    neighbors: SpatialHash,
+    noise: PerlinNoise,
+    seed: u32,
+}
+
+impl Seedable for Steering {
+    fn set_seed(&mut self, seed: u32) -> &mut Self {
+        self.seed = seed;
+        self.noise = perlin(seed);
+        self
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+impl Steering {
+    pub const DEFAULT_SEED: u32 = 0;
+
+    // max_speed : speed the behaviors aim for
+    // max_force : limit on each behavior's steering force, before its weight
+    pub fn new(max_speed: f32, max_force: f32) -> Self {
+        Steering {
+            max_speed: max_speed.max(0.0),
+            max_force: max_force.max(0.0),
+            behaviors: Vec::new(),
+            neighbors: SpatialHash::new(1.0),
+            noise: perlin(Self::DEFAULT_SEED),
+            seed: Self::DEFAULT_SEED,
+        }
+    }
+
+    pub fn add(&mut self, behavior: Behavior, weight: f32) -> &mut Self {
+        self.behaviors.push((behavior, weight));
+        self
+    }
+
+    pub fn clear(&mut self) -> &mut Self {
+        self.behaviors.clear();
+        self
+    }
+
+    pub fn behaviors(&self) -> &[(Behavior, f32)] {
+        &self.behaviors
+    }
+
+    // the behaviors, e.g. to move a seek target every frame
+    pub fn behaviors_mut(&mut self) -> &mut Vec<(Behavior, f32)> {
+        &mut self.behaviors
+    }
+
+    // applies the weighted steering forces to every particle at time t (seconds);
+    // `field` is what `Behavior::Follow` follows, `&()` if there is none
+    pub fn steer<T>(&mut self, particles: &mut [Particle<T>], t: f64, field: &impl ParticleForce) {
+        let radius = self
+            .behaviors
+            .iter()
+            .filter_map(|(behavior, _)| match behavior {
+                Behavior::Separation { radius }
+                | Behavior::Alignment { radius }
+                | Behavior::Cohesion { radius } => Some(*radius),
+                _ => None,
+            })
+            .fold(0.0, f32::max);
+
+        if radius > 0.0 {
+            if (self.neighbors.size() - radius).abs() > f32::EPSILON {
+                self.neighbors = SpatialHash::new(radius);
+            }
+            self.neighbors.clear();
+            particles.iter().for_each(|p| {
+                self.neighbors.insert(p.position);
+            });
+        }
+
+        let forces: Vec<Vec2> = (0..particles.len())
+            .map(|n| {
+                let neighbors = if radius > 0.0 {
+                    let mut found = Vec::new();
+                    self.neighbors
+                        .for_each_within(particles[n].position, radius, |m, _| {
+                            if m != n {
+                                found.push(m);
+                            }
+                        });
+                    found
+                } else {
+                    Vec::new()
+                };
+
+                self.behaviors
+                    .iter()
+                    .map(|(behavior, weight)| {
+                        self.force(behavior, n, particles, &neighbors, t, field) * *weight
+                    })
+                    .fold(Vec2::ZERO, |a, f| a + f)
+            })
+            .collect();
+
+        for (particle, force) in particles.iter_mut().zip(forces) {
+            particle.apply_force(force);
+        }
+    }
+
+    // steering force of one behavior on particle n
+    fn force<T>(
+        &self,
+        behavior: &Behavior,
+        n: usize,
+        particles: &[Particle<T>],
+        neighbors: &[usize],
+        t: f64,
+        field: &impl ParticleForce,
+    ) -> Vec2 {
+        let me = &particles[n];
+        let (p, v) = (me.position, me.velocity);
+        let within = |r: f32| {
+            neighbors
+                .iter()
+                .map(move |m| &particles[*m])
+                .filter(move |other| other.position.distance_squared(p) <= r * r)
+        };
+
+        let desired = match behavior {
+            Behavior::Separation { radius } => {
+                let away = within(*radius)
+                    .map(|other| {
+                        let d = p - other.position;
+                        d / d.length_squared().max(f32::EPSILON)
+                    })
+                    .fold(Vec2::ZERO, |a, d| a + d);
+                if away == Vec2::ZERO {
+                    return Vec2::ZERO;
+                }
+                away.normalize_or_zero() * self.max_speed
+            }
+            Behavior::Alignment { radius } => {
+                let (sum, count) = within(*radius)
+                    .fold((Vec2::ZERO, 0), |(s, c), other| (s + other.velocity, c + 1));
+                if count == 0 {
+                    return Vec2::ZERO;
+                }
+                (sum / count as f32).normalize_or_zero() * self.max_speed
+            }
+            Behavior::Cohesion { radius } => {
+                let (sum, count) = within(*radius)
+                    .fold((Vec2::ZERO, 0), |(s, c), other| (s + other.position, c + 1));
+                if count == 0 {
+                    return Vec2::ZERO;
+                }
+                (sum / count as f32 - p).normalize_or_zero() * self.max_speed
+            }
+            Behavior::Seek(target) => (*target - p).normalize_or_zero() * self.max_speed,
+            Behavior::Flee { target, radius } => {
+                if p.distance_squared(*target) > radius * radius {
+                    return Vec2::ZERO;
+                }
+                (p - *target).normalize_or_zero() * self.max_speed
+            }
+            Behavior::Arrive { target, slowing } => {
+                let offset = *target - p;
+                let ramp = (offset.length() / slowing.max(f32::EPSILON)).min(1.0);
+                offset.normalize_or_zero() * self.max_speed * ramp
+            }
+            Behavior::Wander { scale, rate } => {
+                let q = p / scale.max(f32::EPSILON);
+                let turn = self.noise.get([q.x as f64, q.y as f64, t * *rate as f64]) as f32;
+                let h = heading(v);
+                let angle = h.y.atan2(h.x) + turn * PI;
+                Vec2::new(angle.cos(), angle.sin()) * self.max_speed
+            }
+            Behavior::Avoid {
+                obstacles,
+                look_ahead,
+            } => {
+                let ahead = heading(v);
+                // the obstacle met first along the heading, and the closest point to its center
+                let threat = obstacles
+                    .iter()
+                    .filter_map(|(center, r)| {
+                        let s = (*center - p).dot(ahead).clamp(0.0, *look_ahead);
+                        let closest = p + ahead * s;
+                        (closest.distance_squared(*center) < r * r).then_some((s, closest, *center))
+                    })
+                    .min_by(|a, b| a.0.total_cmp(&b.0));
+                let Some((s, closest, center)) = threat else {
+                    return Vec2::ZERO;
+                };
+                // steer harder the closer the obstacle is
+                let urgency = 1.0 - s / look_ahead.max(f32::EPSILON);
+                let mut away = (closest - center).normalize_or_zero();
+                if away == Vec2::ZERO {
+                    away = ahead.perp();
+                }
+                return ((away * self.max_speed - v) * urgency).clamp_length_max(self.max_force);
+            }
+            Behavior::Follow => field.force_at(p, t).normalize_or_zero() * self.max_speed,
+        };
+
+        (desired - v).clamp_length_max(self.max_force)
+    }
+}
+
+// direction of travel, +x for particles at rest
+fn heading(velocity: Vec2) -> Vec2 {
+    let h = velocity.normalize_or_zero();
+    if h == Vec2::ZERO {
+        Vec2::X
+    } else {
+        h
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: f32, y: f32) -> Particle {
+        Particle::new(Vec2::new(x, y))
+    }
+
+    #[test]
+    fn seek_and_flee_are_limited_to_max_force() {
+        let mut steering = Steering::new(10.0, 4.0);
+        steering.add(Behavior::Seek(Vec2::new(100.0, 0.0)), 1.0);
+        let mut particles = vec![at(0.0, 0.0)];
+        steering.steer(&mut particles, 0.0, &());
+        let force = particles[0].acceleration;
+        assert!(force.x > 0.0 && force.y.abs() < 1e-5);
+        assert!(force.length() <= 4.0 + 1e-4);
+
+        let mut steering = Steering::new(10.0, 4.0);
+        steering.add(
+            Behavior::Flee {
+                target: Vec2::ZERO,
+                radius: 5.0,
+            },
+            1.0,
+        );
+        let mut particles = vec![at(2.0, 0.0), at(50.0, 0.0)];
+        steering.steer(&mut particles, 0.0, &());
+        assert!(particles[0].acceleration.x > 0.0);
+        assert_eq!(particles[1].acceleration, Vec2::ZERO);
+    }
+
+    #[test]
+    fn separation_and_cohesion_only_see_neighbours() {
+        let mut steering = Steering::new(10.0, 100.0);
+        steering.add(Behavior::Separation { radius: 5.0 }, 1.0);
+        let mut particles = vec![at(0.0, 0.0), at(2.0, 0.0), at(40.0, 0.0)];
+        steering.steer(&mut particles, 0.0, &());
+        assert!(particles[0].acceleration.x < 0.0);
+        assert!(particles[1].acceleration.x > 0.0);
+        assert_eq!(particles[2].acceleration, Vec2::ZERO);
+
+        let mut steering = Steering::new(10.0, 100.0);
+        steering.add(Behavior::Cohesion { radius: 5.0 }, 1.0);
+        let mut particles = vec![at(0.0, 0.0), at(2.0, 0.0)];
+        steering.steer(&mut particles, 0.0, &());
+        assert!(particles[0].acceleration.x > 0.0);
+        assert!(particles[1].acceleration.x < 0.0);
+    }
+
+    #[test]
+    fn wander_is_reproducible_for_a_seed() {
+        let run = |seed: u32| {
+            let mut steering = Steering::new(10.0, 5.0);
+            steering
+                .add(
+                    Behavior::Wander {
+                        scale: 20.0,
+                        rate: 1.0,
+                    },
+                    1.0,
+                )
+                .set_seed(seed);
+            let mut particles: Vec<Particle> = (0..5).map(|n| at(n as f32 * 7.3, 1.0)).collect();
+            steering.steer(&mut particles, 0.5, &());
+            particles.iter().map(|p| p.acceleration).collect::<Vec<_>>()
+        };
+        assert_eq!(run(3), run(3));
+        assert_ne!(run(3), run(4));
+    }
+}
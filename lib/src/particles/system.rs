@@ -0,0 +1,373 @@
+use crate::common::Seedable;
+use crate::forces::field::ForceField;
+use crate::forces::map::FlowField;
+use crate::particles::particle::Particle;
+use crate::utils::rng;
+use nannou::glam::Vec2;
+use nannou::rand::rngs::StdRng;
+
+// Anything that pushes particles around, sampled at world positions and time t (seconds).
+// A baked `FlowField` is sampled bilinearly, a raw `ForceField` goes through `FieldForce`,
+// and closures `|position, t| ...` work too, e.g. to add gravity to a field.
+pub trait ParticleForce {
+    fn force_at(&self, position: Vec2, t: f64) -> Vec2;
+}
+
+impl<F: Fn(Vec2, f64) -> Vec2> ParticleForce for F {
+    fn force_at(&self, position: Vec2, t: f64) -> Vec2 {
+        self(position, t)
+    }
+}
+
+// no force at all, particles keep their velocity (minus drag)
+impl ParticleForce for () {
+    fn force_at(&self, _position: Vec2, _t: f64) -> Vec2 {
+        Vec2::ZERO
+    }
+}
+
+impl ParticleForce for FlowField {
+    fn force_at(&self, position: Vec2, _t: f64) -> Vec2 {
+        self.sample(position.into())
+    }
+}
+
+// A `ForceField` evaluated directly (not baked) at the field indices of a `FlowField` grid,
+// smoother than the baked grid and animated by the system's time.
+pub struct FieldForce<'a, F> {
+    force: &'a F,
+    origin: Vec2,
+    resolution: f32,
+}
+
+impl<'a, F: ForceField> FieldForce<'a, F> {
+    pub fn new(force: &'a F, grid: &FlowField) -> Self {
+        FieldForce {
+            force,
+            origin: grid.point_at(0, 0),
+            resolution: grid.resolution(),
+        }
+    }
+
+    // without a grid: world = index * resolution, from the origin
+    pub fn with_resolution(force: &'a F, resolution: f32) -> Self {
+        FieldForce {
+            force,
+            origin: Vec2::ZERO,
+            resolution: resolution.max(f32::EPSILON),
+        }
+    }
+}
+
+impl<F: ForceField> ParticleForce for FieldForce<'_, F> {
+    fn force_at(&self, position: Vec2, t: f64) -> Vec2 {
+        let index = (position - self.origin) / self.resolution;
+        self.force.get_at(index.x as f64, index.y as f64, t)
+    }
+}
+
+// What happens to particles that leave the bounds
+//
+// Kill   : they die (and respawn, if the system has a spawner)
+// Wrap   : they come back in on the opposite side
+// Bounce : they are reflected back in, losing no speed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edges {
+    Kill,
+    Wrap,
+    Bounce,
+}
+
+// Makes a new particle to replace a dead one, from the system's seeded rng.
+pub type Spawner<T> = Box<dyn FnMut(&mut StdRng) -> Particle<T>>;
+
+// Particles stepped with a fixed timestep: `update` is given the real elapsed time and runs
+// as many steps of `timestep` seconds as fit in it, so the motion does not depend on the frame rate.
+//
+//     let mut system = ParticleSystem::new(1.0 / 120.0);
+//     system.drag(0.5).max_speed(200.0).bounds(min, max, Edges::Wrap);
+//     system.update(update.since_last.as_secs_f32(), &field);
+pub struct ParticleSystem<T = ()> {
+    particles: Vec<Particle<T>>,
+    timestep: f32,
+    accumulator: f32,
+    time: f64,
+    drag: f32,
+    max_speed: Option<f32>,
+    bounds: Option<([Vec2; 2], Edges)>,
+    spawner: Option<Spawner<T>>,
+    rng: StdRng,
+    seed: u32,
+}
+
+impl<T> Seedable for ParticleSystem<T> {
+    // also restarts the random sequence used to respawn particles
+    fn set_seed(&mut self, seed: u32) -> &mut Self {
+        self.seed = seed;
+        self.rng = rng(seed as u64);
+        self
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+impl<T> ParticleSystem<T> {
+    pub const DEFAULT_SEED: u32 = 0;
+    // steps behind are dropped beyond this, so a long frame does not freeze the sketch
+    pub const MAX_STEPS_PER_UPDATE: usize = 32;
+
+    pub fn new(timestep: f32) -> Self {
+        ParticleSystem {
+            particles: Vec::new(),
+            timestep: timestep.max(f32::EPSILON),
+            accumulator: 0.0,
+            time: 0.0,
+            drag: 0.0,
+            max_speed: None,
+            bounds: None,
+            spawner: None,
+            rng: rng(Self::DEFAULT_SEED as u64),
+            seed: Self::DEFAULT_SEED,
+        }
+    }
+
+    // fraction of the velocity lost per second
+    pub fn drag(&mut self, drag: f32) -> &mut Self {
+        self.drag = drag.max(0.0);
+        self
+    }
+
+    pub fn max_speed(&mut self, speed: f32) -> &mut Self {
+        self.max_speed = Some(speed.max(0.0));
+        self
+    }
+
+    pub fn bounds(&mut self, min: Vec2, max: Vec2, edges: Edges) -> &mut Self {
+        self.bounds = Some(([min.min(max), min.max(max)], edges));
+        self
+    }
+
+    // replace every dead particle with a new one from `spawner`, keeping the count steady
+    pub fn respawn(
+        &mut self,
+        spawner: impl FnMut(&mut StdRng) -> Particle<T> + 'static,
+    ) -> &mut Self {
+        self.spawner = Some(Box::new(spawner));
+        self
+    }
+
+    pub fn spawn(&mut self, particle: Particle<T>) -> &mut Self {
+        self.particles.push(particle);
+        self
+    }
+
+    // add `count` particles from the respawn spawner, if there is one
+    pub fn populate(&mut self, count: usize) -> &mut Self {
+        if let Some(spawner) = &mut self.spawner {
+            for _ in 0..count {
+                self.particles.push(spawner(&mut self.rng));
+            }
+        }
+        self
+    }
+
+    pub fn clear(&mut self) -> &mut Self {
+        self.particles.clear();
+        self.accumulator = 0.0;
+        self.time = 0.0;
+        self
+    }
+
+    pub fn particles(&self) -> &[Particle<T>] {
+        &self.particles
+    }
+
+    pub fn particles_mut(&mut self) -> &mut Vec<Particle<T>> {
+        &mut self.particles
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    // seconds simulated so far
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn timestep(&self) -> f32 {
+        self.timestep
+    }
+
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+
+    // advance by `elapsed` seconds in fixed steps, returns how many steps were run
+    pub fn update(&mut self, elapsed: f32, force: &impl ParticleForce) -> usize {
+        self.update_with(elapsed, force, |_, _| {})
+    }
+
+    // like `update`, calling `before(particles, t)` ahead of every step, for forces that
+    // depend on more than a position, e.g. `Steering` which looks at velocities and neighbours
+    pub fn update_with(
+        &mut self,
+        elapsed: f32,
+        force: &impl ParticleForce,
+        mut before: impl FnMut(&mut [Particle<T>], f64),
+    ) -> usize {
+        self.accumulator += elapsed.max(0.0);
+        let mut steps = 0;
+        while self.accumulator >= self.timestep {
+            self.accumulator -= self.timestep;
+            if steps < Self::MAX_STEPS_PER_UPDATE {
+                before(&mut self.particles, self.time);
+                self.step(force);
+                steps += 1;
+            }
+        }
+        steps
+    }
+
+    // a single step of `timestep` seconds
+    pub fn step(&mut self, force: &impl ParticleForce) -> &mut Self {
+        let dt = self.timestep;
+        let damping = (1.0 - self.drag * dt).max(0.0);
+
+        for particle in self.particles.iter_mut() {
+            let f = force.force_at(particle.position, self.time);
+            particle
+                .apply_force(f)
+                .step_damped(dt, damping, self.max_speed);
+        }
+
+        let mut dead = 0;
+        let bounds = self.bounds;
+        self.particles.retain_mut(|particle| {
+            let outside = match bounds {
+                Some((bounds, edges)) => !keep_inside(particle, bounds, edges),
+                None => false,
+            };
+            let alive = !outside && !particle.is_dead();
+            dead += !alive as usize;
+            alive
+        });
+
+        if let Some(spawner) = &mut self.spawner {
+            for _ in 0..dead {
+                self.particles.push(spawner(&mut self.rng));
+            }
+        }
+
+        self.time += dt as f64;
+        self
+    }
+}
+
+// applies wrapping or bouncing; false when the particle is out and should die
+fn keep_inside<T>(particle: &mut Particle<T>, [min, max]: [Vec2; 2], edges: Edges) -> bool {
+    let p = particle.position;
+    if p.cmpge(min).all() && p.cmple(max).all() {
+        return true;
+    }
+
+    match edges {
+        Edges::Kill => false,
+        Edges::Wrap => {
+            let size = (max - min).max(Vec2::splat(f32::EPSILON));
+            particle.position = Vec2::new(
+                min.x + (p.x - min.x).rem_euclid(size.x),
+                min.y + (p.y - min.y).rem_euclid(size.y),
+            );
+            true
+        }
+        Edges::Bounce => {
+            let v = &mut particle.velocity;
+            if p.x < min.x || p.x > max.x {
+                v.x = -v.x;
+            }
+            if p.y < min.y || p.y > max.y {
+                v.y = -v.y;
+            }
+            particle.position = p.clamp(min, max);
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_steps_under_a_constant_force() {
+        let mut system: ParticleSystem = ParticleSystem::new(0.1);
+        system.spawn(Particle::new(Vec2::ZERO));
+
+        // 0.35 s runs three steps and keeps the rest for the next update
+        let gravity = |_: Vec2, _: f64| Vec2::new(0.0, 10.0);
+        assert_eq!(system.update(0.35, &gravity), 3);
+        assert!((system.time() - 0.3).abs() < 1e-6);
+
+        // semi-implicit euler: v = 1, 2, 3 and y = 0.1 + 0.2 + 0.3
+        let particle = &system.particles()[0];
+        assert!((particle.velocity.y - 3.0).abs() < 1e-5);
+        assert!((particle.position.y - 0.6).abs() < 1e-5);
+        assert!((particle.age - 0.3).abs() < 1e-5);
+    }
+
+    #[test]
+    fn drag_and_max_speed_limit_the_velocity() {
+        let mut system: ParticleSystem = ParticleSystem::new(0.1);
+        system.drag(5.0).max_speed(2.0);
+        system.spawn(Particle::new(Vec2::ZERO));
+
+        system.step(&|_: Vec2, _: f64| Vec2::new(100.0, 0.0));
+        // (0 + 100 * 0.1) * (1 - 5 * 0.1) = 5, then clamped to 2
+        assert!((system.particles()[0].velocity.x - 2.0).abs() < 1e-5);
+        assert!((system.particles()[0].position.x - 0.2).abs() < 1e-5);
+    }
+
+    #[test]
+    fn dead_particles_are_respawned() {
+        let mut system: ParticleSystem = ParticleSystem::new(0.1);
+        system
+            .respawn(|_| {
+                let mut particle = Particle::new(Vec2::ZERO);
+                particle.lifetime(0.25);
+                particle
+            })
+            .populate(10);
+
+        for _ in 0..3 {
+            system.step(&());
+        }
+        // all of them died after the third step and were replaced by newborns
+        assert_eq!(system.len(), 10);
+        assert!(system.particles().iter().all(|p| p.age == 0.0));
+    }
+
+    #[test]
+    fn edges_wrap_bounce_and_kill() {
+        let run = |edges: Edges| {
+            let mut system: ParticleSystem = ParticleSystem::new(0.1);
+            system.bounds(Vec2::ZERO, Vec2::splat(10.0), edges);
+            let mut particle = Particle::new(Vec2::new(9.5, 5.0));
+            particle.velocity(Vec2::new(10.0, 0.0));
+            system.spawn(particle).step(&());
+            system.particles().first().cloned()
+        };
+
+        assert!(run(Edges::Kill).is_none());
+        let wrapped = run(Edges::Wrap).unwrap();
+        assert!((wrapped.position.x - 0.5).abs() < 1e-4);
+        let bounced = run(Edges::Bounce).unwrap();
+        assert!(bounced.position.x <= 10.0 && bounced.velocity.x < 0.0);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::spatial::{Best, Neighbors};
+use nannou::glam::Vec2;
+use std::collections::HashMap;
+
+// Points bucketed in an unbounded grid of square cells, keyed by cell coordinates.
+// Inserting, moving and removing a point is O(1), a radius query only looks at the cells
+// the radius overlaps, so the cell size is best close to the usual query radius.
+//
+// `insert` hands out the index a point is known by; indices of removed points are reused.
+//
+//     let mut hash = SpatialHash::new(20.0);
+//     let ids: Vec<usize> = points.iter().map(|p| hash.insert(*p)).collect();
+//     hash.set(ids[0], moved);
+//     let close = hash.within(p, 20.0);
+#[derive(Clone, Debug)]
+pub struct SpatialHash {
+    size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    points: Vec<Option<Vec2>>,
+    free: Vec<usize>,
+}
+
+impl SpatialHash {
+    pub fn new(size: f32) -> Self {
+        SpatialHash {
+            size: size.max(f32::EPSILON),
+            cells: HashMap::new(),
+            points: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    // a hash holding `points`, indexed like the slice
+    pub fn from_points(size: f32, points: &[Vec2]) -> Self {
+        let mut hash = SpatialHash::new(size);
+        points.iter().for_each(|p| {
+            hash.insert(*p);
+        });
+        hash
+    }
+
+    pub fn insert(&mut self, point: Vec2) -> usize {
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.points[index] = Some(point);
+                index
+            }
+            None => {
+                self.points.push(Some(point));
+                self.points.len() - 1
+            }
+        };
+        self.add_to_cell(point, index);
+        index
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Vec2> {
+        let point = self.points.get_mut(index)?.take()?;
+        self.remove_from_cell(point, index);
+        self.free.push(index);
+        Some(point)
+    }
+
+    // move a point, false if there is no point with that index
+    pub fn set(&mut self, index: usize, point: Vec2) -> bool {
+        let Some(Some(old)) = self.points.get(index).copied() else {
+            return false;
+        };
+        if self.cell_of(old) != self.cell_of(point) {
+            self.remove_from_cell(old, index);
+            self.add_to_cell(point, index);
+        }
+        self.points[index] = Some(point);
+        true
+    }
+
+    pub fn get(&self, index: usize) -> Option<Vec2> {
+        self.points.get(index).copied().flatten()
+    }
+
+    pub fn clear(&mut self) -> &mut Self {
+        self.cells.clear();
+        self.points.clear();
+        self.free.clear();
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    // (index, point) of every point
+    pub fn iter(&self) -> impl Iterator<Item = (usize, Vec2)> + '_ {
+        self.points
+            .iter()
+            .enumerate()
+            .filter_map(|(n, p)| p.map(|p| (n, p)))
+    }
+
+    // calls `f(index, point)` for every point within `radius` of `center`,
+    // without collecting them
+    pub fn for_each_within(&self, center: Vec2, radius: f32, mut f: impl FnMut(usize, Vec2)) {
+        let r2 = radius * radius;
+        let (i0, j0) = self.cell_of(center - Vec2::splat(radius));
+        let (i1, j1) = self.cell_of(center + Vec2::splat(radius));
+        for j in j0..=j1 {
+            for i in i0..=i1 {
+                for index in self.cells.get(&(i, j)).into_iter().flatten() {
+                    let point = self.points[*index].unwrap();
+                    if point.distance_squared(center) <= r2 {
+                        f(*index, point);
+                    }
+                }
+            }
+        }
+    }
+
+    // whether any point is closer than `radius` to `center`
+    pub fn any_within(&self, center: Vec2, radius: f32) -> bool {
+        let r2 = radius * radius;
+        let (i0, j0) = self.cell_of(center - Vec2::splat(radius));
+        let (i1, j1) = self.cell_of(center + Vec2::splat(radius));
+        (j0..=j1).any(|j| {
+            (i0..=i1).any(|i| {
+                self.cells.get(&(i, j)).is_some_and(|cell| {
+                    cell.iter()
+                        .any(|n| self.points[*n].unwrap().distance_squared(center) < r2)
+                })
+            })
+        })
+    }
+
+    fn cell_of(&self, point: Vec2) -> (i32, i32) {
+        let p = (point / self.size).floor();
+        (p.x as i32, p.y as i32)
+    }
+
+    fn add_to_cell(&mut self, point: Vec2, index: usize) {
+        let cell = self.cell_of(point);
+        self.cells.entry(cell).or_default().push(index);
+    }
+
+    fn remove_from_cell(&mut self, point: Vec2, index: usize) {
+        let cell = self.cell_of(point);
+        if let Some(indices) = self.cells.get_mut(&cell) {
+            if let Some(at) = indices.iter().position(|n| *n == index) {
+                indices.swap_remove(at);
+            }
+            if indices.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+    }
+}
+
+impl Neighbors for SpatialHash {
+    fn within(&self, center: Vec2, radius: f32) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.for_each_within(center, radius, |n, _| found.push(n));
+        found
+    }
+
+    // searches rings of cells around the point's cell outwards, until the k-th closest
+    // point found so far is nearer than anything the next ring could hold, or every point
+    // has been seen; once the rings have covered more cells than are occupied, the occupied
+    // cells left outside them are scanned directly instead, so far apart points stay cheap
+    fn nearest(&self, point: Vec2, k: usize) -> Vec<usize> {
+        let mut best = Best::new(k);
+        if k == 0 || self.is_empty() {
+            return best.indices();
+        }
+
+        let (ci, cj) = self.cell_of(point);
+        // pushes the points of a cell, returns how many there were
+        let visit = |best: &mut Best, cell: (i32, i32)| {
+            let indices = self
+                .cells
+                .get(&cell)
+                .map_or(&[][..], |cell| cell.as_slice());
+            for index in indices {
+                let d2 = self.points[*index].unwrap().distance_squared(point);
+                best.push(d2, *index);
+            }
+            indices.len()
+        };
+
+        let mut visited = visit(&mut best, (ci, cj));
+        let mut scanned = 1;
+        for ring in 1.. {
+            if visited >= self.len() {
+                break;
+            }
+
+            if scanned > self.cells.len() {
+                // Chebyshev distance in cells, beyond the last ring searched
+                let outside = |(i, j): (i32, i32)| {
+                    let di = (i as i64 - ci as i64).abs();
+                    let dj = (j as i64 - cj as i64).abs();
+                    di.max(dj) >= ring as i64
+                };
+                for cell in self.cells.keys().filter(|cell| outside(**cell)) {
+                    visit(&mut best, *cell);
+                }
+                break;
+            }
+
+            // only the cells on the ring's perimeter: full top and bottom rows,
+            // then the left and right columns between them; rows and columns past
+            // the range of i32 cannot hold points and are skipped
+            let columns = ci.saturating_sub(ring)..=ci.saturating_add(ring);
+            for j in [cj.checked_sub(ring), cj.checked_add(ring)]
+                .into_iter()
+                .flatten()
+            {
+                for i in columns.clone() {
+                    visited += visit(&mut best, (i, j));
+                }
+            }
+            let rows = cj.saturating_sub(ring - 1)..=cj.saturating_add(ring - 1);
+            for i in [ci.checked_sub(ring), ci.checked_add(ring)]
+                .into_iter()
+                .flatten()
+            {
+                for j in rows.clone() {
+                    visited += visit(&mut best, (i, j));
+                }
+            }
+            scanned += 8 * ring as usize;
+
+            // cells beyond this ring are at least `ring` cells away
+            let beyond = ring as f32 * self.size;
+            if best.is_full() && best.worst() <= beyond * beyond {
+                break;
+            }
+        }
+
+        best.indices()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::rng;
+    use nannou::rand::Rng;
+
+    fn random_points(count: usize, seed: u64) -> Vec<Vec2> {
+        let mut rng = rng(seed);
+        (0..count)
+            .map(|_| Vec2::new(rng.gen_range(-100.0..300.0), rng.gen_range(-50.0..50.0)))
+            .collect()
+    }
+
+    // distances of the k closest points, by sorting them all
+    fn brute_nearest(points: &[Vec2], point: Vec2, k: usize) -> Vec<f32> {
+        let mut distances: Vec<f32> = points.iter().map(|p| p.distance(point)).collect();
+        distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        distances.truncate(k);
+        distances
+    }
+
+    #[test]
+    fn queries_match_a_brute_force_search() {
+        let points = random_points(500, 1);
+        let hash = SpatialHash::from_points(10.0, &points);
+
+        for (n, query) in random_points(50, 2).into_iter().enumerate() {
+            let mut within = hash.within(query, 25.0);
+            within.sort_unstable();
+            let expected: Vec<usize> = (0..points.len())
+                .filter(|n| points[*n].distance(query) <= 25.0)
+                .collect();
+            assert_eq!(within, expected);
+
+            for k in [1, 7, n * 10, 500, 600] {
+                let found: Vec<f32> = hash
+                    .nearest(query, k)
+                    .iter()
+                    .map(|n| points[*n].distance(query))
+                    .collect();
+                assert_eq!(found, brute_nearest(&points, query, k), "k = {}", k);
+            }
+        }
+    }
+
+    #[test]
+    fn removed_indices_are_reused() {
+        let mut hash = SpatialHash::new(5.0);
+        let a = hash.insert(Vec2::new(1.0, 1.0));
+        let b = hash.insert(Vec2::new(50.0, 1.0));
+        let c = hash.insert(Vec2::new(100.0, 1.0));
+        assert_eq!((a, b, c, hash.len()), (0, 1, 2, 3));
+
+        assert_eq!(hash.remove(b), Some(Vec2::new(50.0, 1.0)));
+        assert_eq!(hash.remove(b), None);
+        assert_eq!(hash.get(b), None);
+        assert!(!hash.set(b, Vec2::ZERO));
+        assert_eq!(hash.len(), 2);
+        assert!(hash.within(Vec2::new(50.0, 1.0), 10.0).is_empty());
+        assert_eq!(hash.closest(Vec2::new(60.0, 1.0)), Some(c));
+
+        assert_eq!(hash.insert(Vec2::new(-20.0, 0.0)), b);
+        assert!(hash.set(a, Vec2::new(80.0, 0.0)));
+        assert_eq!(hash.closest(Vec2::new(75.0, 0.0)), Some(a));
+        assert_eq!(hash.nearest(Vec2::ZERO, 10), vec![b, a, c]);
+
+        hash.clear();
+        assert!(hash.is_empty() && hash.nearest(Vec2::ZERO, 3).is_empty());
+    }
+
+    #[test]
+    fn far_apart_points_are_found_quickly() {
+        let mut hash = SpatialHash::new(1.0);
+        hash.insert(Vec2::ZERO);
+        hash.insert(Vec2::splat(20000.0));
+
+        // every ring out to either point would be 10^8 cells
+        assert_eq!(hash.nearest(Vec2::splat(10000.0), 5).len(), 2);
+        assert_eq!(hash.closest(Vec2::splat(9000.0)), Some(0));
+
+        // a removed point far out does not keep the search going
+        hash.remove(1);
+        assert_eq!(hash.nearest(Vec2::splat(-5000.0), 3), vec![0]);
+        assert_eq!(hash.nearest(Vec2::splat(f32::MAX), 1), vec![0]);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::spatial::{Best, Neighbors};
+use nannou::glam::Vec2;
+
+// A balanced 2-d tree over a fixed set of points, built once in O(n log n).
+// Queries return indices into the slice the tree was built from.
+//
+// The tree is static: `insert` and `remove` only record the change until `rebuild`.
+// Queries on a stale tree still answer right, skipping removed points and scanning the
+// ones inserted since the last `rebuild` one by one, so rebuild after large batches.
+//
+//     let tree = KdTree::new(&points);
+//     let close = tree.nearest(p, 5);
+#[derive(Clone, Debug, Default)]
+pub struct KdTree {
+    points: Vec<Vec2>,
+    removed: Vec<bool>,
+    // point indices, each range [lo, hi) is a subtree with its root at the middle,
+    // split on x at even depths and on y at odd ones
+    nodes: Vec<usize>,
+    // points inserted since the last rebuild start here
+    built: usize,
+    stale: bool,
+}
+
+impl KdTree {
+    pub fn new(points: &[Vec2]) -> Self {
+        let mut tree = KdTree {
+            points: points.to_vec(),
+            removed: vec![false; points.len()],
+            nodes: Vec::new(),
+            built: 0,
+            stale: true,
+        };
+        tree.rebuild();
+        tree
+    }
+
+    // adds a point, returns its index
+    pub fn insert(&mut self, point: Vec2) -> usize {
+        self.points.push(point);
+        self.removed.push(false);
+        self.stale = true;
+        self.points.len() - 1
+    }
+
+    // indices are never reused, the point is only left out of later queries
+    pub fn remove(&mut self, index: usize) -> Option<Vec2> {
+        if *self.removed.get(index)? {
+            return None;
+        }
+        self.removed[index] = true;
+        self.stale = true;
+        Some(self.points[index])
+    }
+
+    pub fn get(&self, index: usize) -> Option<Vec2> {
+        match self.removed.get(index) {
+            Some(false) => Some(self.points[index]),
+            _ => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.removed.iter().filter(|r| !**r).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // rebuilds after inserts or removals, queries on a stale tree scan the inserted points
+    pub fn rebuild(&mut self) -> &mut Self {
+        self.nodes = (0..self.points.len())
+            .filter(|n| !self.removed[*n])
+            .collect();
+        let len = self.nodes.len();
+        build(&self.points, &mut self.nodes, 0, len, 0);
+        self.built = self.points.len();
+        self.stale = false;
+        self
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    // points inserted since the last rebuild and not removed since
+    fn pending(&self) -> impl Iterator<Item = usize> + '_ {
+        (self.built..self.points.len()).filter(|n| !self.removed[*n])
+    }
+
+    fn search_within(
+        &self,
+        lo: usize,
+        hi: usize,
+        depth: usize,
+        q: (Vec2, f32),
+        out: &mut Vec<usize>,
+    ) {
+        if lo >= hi {
+            return;
+        }
+        let (center, radius) = q;
+        let mid = (lo + hi) / 2;
+        let index = self.nodes[mid];
+        let point = self.points[index];
+        if !self.removed[index] && point.distance_squared(center) <= radius * radius {
+            out.push(index);
+        }
+
+        let delta = axis(center, depth) - axis(point, depth);
+        if delta - radius <= 0.0 {
+            self.search_within(lo, mid, depth + 1, q, out);
+        }
+        if delta + radius >= 0.0 {
+            self.search_within(mid + 1, hi, depth + 1, q, out);
+        }
+    }
+
+    fn search_nearest(&self, lo: usize, hi: usize, depth: usize, point: Vec2, best: &mut Best) {
+        if lo >= hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        let index = self.nodes[mid];
+        if !self.removed[index] {
+            best.push(self.points[index].distance_squared(point), index);
+        }
+
+        // the side holding the point first, the other only if it can hold something closer
+        let delta = axis(point, depth) - axis(self.points[index], depth);
+        let (near, far) = if delta < 0.0 {
+            ((lo, mid), (mid + 1, hi))
+        } else {
+            ((mid + 1, hi), (lo, mid))
+        };
+        self.search_nearest(near.0, near.1, depth + 1, point, best);
+        if delta * delta < best.worst() {
+            self.search_nearest(far.0, far.1, depth + 1, point, best);
+        }
+    }
+}
+
+impl Neighbors for KdTree {
+    fn within(&self, center: Vec2, radius: f32) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.search_within(0, self.nodes.len(), 0, (center, radius), &mut found);
+        found.extend(
+            self.pending()
+                .filter(|n| self.points[*n].distance_squared(center) <= radius * radius),
+        );
+        found
+    }
+
+    fn nearest(&self, point: Vec2, k: usize) -> Vec<usize> {
+        let mut best = Best::new(k);
+        if k > 0 {
+            self.search_nearest(0, self.nodes.len(), 0, point, &mut best);
+            for n in self.pending() {
+                best.push(self.points[n].distance_squared(point), n);
+            }
+        }
+        best.indices()
+    }
+}
+
+fn axis(point: Vec2, depth: usize) -> f32 {
+    if depth.is_multiple_of(2) {
+        point.x
+    } else {
+        point.y
+    }
+}
+
+// puts the median of [lo, hi) on the split axis in the middle, smaller ones before it
+fn build(points: &[Vec2], nodes: &mut [usize], lo: usize, hi: usize, depth: usize) {
+    if hi - lo <= 1 {
+        return;
+    }
+    let mid = (lo + hi) / 2;
+    nodes[lo..hi].select_nth_unstable_by(mid - lo, |a, b| {
+        axis(points[*a], depth).total_cmp(&axis(points[*b], depth))
+    });
+    build(points, nodes, lo, mid, depth + 1);
+    build(points, nodes, mid + 1, hi, depth + 1);
+}
@@ -0,0 +1,61 @@
+pub mod hash;
+pub mod kdtree;
+
+use nannou::glam::Vec2;
+
+// Neighbour queries over a set of points, which are referred to by index.
+//
+// `SpatialHash` suits points that move or come and go every frame (particles, boids),
+// `KdTree` suits a fixed set queried many times (plexus lines, nearest seed lookups).
+pub trait Neighbors {
+    // indices of the points within `radius` of `center`, in no particular order
+    fn within(&self, center: Vec2, radius: f32) -> Vec<usize>;
+
+    // indices of the `k` points closest to `point`, closest first
+    fn nearest(&self, point: Vec2, k: usize) -> Vec<usize>;
+
+    fn closest(&self, point: Vec2) -> Option<usize> {
+        self.nearest(point, 1).first().copied()
+    }
+}
+
+// the k best (distance², index) pairs seen so far, kept sorted, closest first
+pub(crate) struct Best {
+    k: usize,
+    items: Vec<(f32, usize)>,
+}
+
+impl Best {
+    pub(crate) fn new(k: usize) -> Self {
+        Best {
+            k,
+            items: Vec::with_capacity(k + 1),
+        }
+    }
+
+    pub(crate) fn push(&mut self, distance2: f32, index: usize) {
+        if self.k == 0 || (self.is_full() && distance2 >= self.worst()) {
+            return;
+        }
+        let at = self.items.partition_point(|(d, _)| *d <= distance2);
+        self.items.insert(at, (distance2, index));
+        self.items.truncate(self.k);
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.items.len() >= self.k
+    }
+
+    // squared distance of the k-th point, infinite until there are k of them
+    pub(crate) fn worst(&self) -> f32 {
+        if self.is_full() {
+            self.items.last().map_or(f32::INFINITY, |(d, _)| *d)
+        } else {
+            f32::INFINITY
+        }
+    }
+
+    pub(crate) fn indices(self) -> Vec<usize> {
+        self.items.into_iter().map(|(_, n)| n).collect()
+    }
+}
@@ -0,0 +1,441 @@
+use nannou::glam::Vec2;
+use nannou::prelude::TAU;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// Minimal SVG reader: pulls the `d` attribute out of every <path> element
+// and flattens it into polylines. Transforms, styles and other shapes are ignored.
+// The writer does the reverse, one <path> per polyline, e.g. for plotters.
+//
+// Supported commands: M L H V C S Q T A Z, absolute and relative.
+// Curves are flattened into CURVE_SEGMENTS straight segments each.
+
+const CURVE_SEGMENTS: usize = 16;
+
+pub type Polyline = Vec<Vec2>;
+
+#[derive(Debug)]
+pub enum SvgError {
+    Io(std::io::Error),
+    Parse { position: usize, message: String },
+}
+
+impl fmt::Display for SvgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SvgError::Io(err) => write!(f, "could not read svg: {}", err),
+            SvgError::Parse { position, message } => {
+                write!(f, "invalid path data at {}: {}", position, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SvgError {}
+
+impl From<std::io::Error> for SvgError {
+    fn from(err: std::io::Error) -> Self {
+        SvgError::Io(err)
+    }
+}
+
+// all paths of an svg file, as polylines in svg user units
+pub fn load_paths(path: impl AsRef<Path>) -> Result<Vec<Polyline>, SvgError> {
+    let contents = fs::read_to_string(path)?;
+    let mut polylines = Vec::new();
+
+    for element in contents.split("<path").skip(1) {
+        let element = &element[..element.find('>').unwrap_or(element.len())];
+        if let Some(d) = attribute(element, "d") {
+            polylines.extend(parse_path(d)?);
+        }
+    }
+
+    Ok(polylines)
+}
+
+// an svg document of `width` x `height` user units with one stroked <path> per polyline,
+// closed ones end with Z
+pub fn save_paths(
+    path: impl AsRef<Path>,
+    width: f32,
+    height: f32,
+    polylines: &[Polyline],
+    closed: bool,
+) -> std::io::Result<()> {
+    let size = Vec2::new(width, height);
+    save_paths_in(path, Vec2::ZERO, size, polylines, closed)
+}
+
+// like `save_paths`, showing the rectangle between the corners `min` and `max`
+// of the polylines' coordinates, e.g. world units centered on the origin
+pub fn save_paths_in(
+    path: impl AsRef<Path>,
+    min: Vec2,
+    max: Vec2,
+    polylines: &[Polyline],
+    closed: bool,
+) -> std::io::Result<()> {
+    let (min, size) = (min.min(max), (max - min).abs());
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"{x} {y} {w} {h}\">\n",
+        x = min.x,
+        y = min.y,
+        w = size.x,
+        h = size.y
+    );
+    for polyline in polylines.iter().filter(|p| p.len() > 1) {
+        svg.push_str(&format!(
+            "  <path d=\"{}\" fill=\"none\" stroke=\"black\" stroke-width=\"1\"/>\n",
+            path_data(polyline, closed)
+        ));
+    }
+    svg.push_str("</svg>\n");
+    fs::write(path, svg)
+}
+
+// the `d` attribute of a polyline: M x y L x y ... (Z)
+pub fn path_data(polyline: &[Vec2], closed: bool) -> String {
+    let mut d = String::new();
+    for (n, p) in polyline.iter().enumerate() {
+        let command = if n == 0 { 'M' } else { 'L' };
+        d.push_str(&format!("{}{:.3} {:.3} ", command, p.x, p.y));
+    }
+    if closed {
+        d.push('Z');
+    }
+    d.trim_end().to_string()
+}
+
+// the value of ` name="..."` (or single-quoted) inside an element's attribute list
+fn attribute<'a>(element: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = element;
+    while let Some(at) = rest.find(name) {
+        let preceded = rest[..at].ends_with(char::is_whitespace);
+        let after = rest[at + name.len()..].trim_start();
+        if preceded && after.starts_with('=') {
+            let after = after[1..].trim_start();
+            let quote = after.chars().next()?;
+            if quote == '"' || quote == '\'' {
+                let value = &after[1..];
+                return value.find(quote).map(|end| &value[..end]);
+            }
+        }
+        rest = &rest[at + name.len()..];
+    }
+    None
+}
+
+// flatten svg path data (the `d` attribute) into polylines, one per subpath
+pub fn parse_path(d: &str) -> Result<Vec<Polyline>, SvgError> {
+    let mut parser = PathParser {
+        src: d.as_bytes(),
+        pos: 0,
+    };
+    let mut polylines: Vec<Polyline> = Vec::new();
+    let mut current: Polyline = Vec::new();
+
+    let mut pen = Vec2::ZERO;
+    let mut start = Vec2::ZERO;
+    // reflected control point for S and T
+    let mut last_control: Option<(u8, Vec2)> = None;
+    let mut command = None;
+    // Z ends the command, the next one must be given again
+    let mut closed = false;
+
+    loop {
+        parser.skip_separators();
+        if parser.done() {
+            break;
+        }
+
+        if let Some(c) = parser.command() {
+            command = Some(c);
+        } else if command.is_none() {
+            return Err(parser.error(if closed {
+                "expected a command after Z"
+            } else {
+                "path data must start with a command"
+            }));
+        }
+
+        let c = command.unwrap();
+        let relative = c.is_ascii_lowercase();
+        let base = if relative { pen } else { Vec2::ZERO };
+        let upper = c.to_ascii_uppercase();
+
+        match upper {
+            b'M' => {
+                finish(&mut polylines, &mut current);
+                pen = base + parser.point()?;
+                start = pen;
+                current.push(pen);
+                // subsequent pairs are implicit line-tos
+                command = Some(if relative { b'l' } else { b'L' });
+            }
+            b'L' => {
+                pen = base + parser.point()?;
+                current.push(pen);
+            }
+            b'H' => {
+                let x = parser.number()?;
+                pen = Vec2::new(if relative { pen.x + x } else { x }, pen.y);
+                current.push(pen);
+            }
+            b'V' => {
+                let y = parser.number()?;
+                pen = Vec2::new(pen.x, if relative { pen.y + y } else { y });
+                current.push(pen);
+            }
+            b'C' | b'S' => {
+                let c1 = if upper == b'C' {
+                    base + parser.point()?
+                } else {
+                    reflect(last_control, b'C', pen)
+                };
+                let c2 = base + parser.point()?;
+                let end = base + parser.point()?;
+                for n in 1..=CURVE_SEGMENTS {
+                    let t = n as f32 / CURVE_SEGMENTS as f32;
+                    let u = 1.0 - t;
+                    current.push(
+                        pen * u * u * u
+                            + c1 * 3.0 * u * u * t
+                            + c2 * 3.0 * u * t * t
+                            + end * t * t * t,
+                    );
+                }
+                last_control = Some((b'C', c2));
+                pen = end;
+            }
+            b'Q' | b'T' => {
+                let c1 = if upper == b'Q' {
+                    base + parser.point()?
+                } else {
+                    reflect(last_control, b'Q', pen)
+                };
+                let end = base + parser.point()?;
+                for n in 1..=CURVE_SEGMENTS {
+                    let t = n as f32 / CURVE_SEGMENTS as f32;
+                    let u = 1.0 - t;
+                    current.push(pen * u * u + c1 * 2.0 * u * t + end * t * t);
+                }
+                last_control = Some((b'Q', c1));
+                pen = end;
+            }
+            b'A' => {
+                let radii = parser.point()?;
+                let rotation = parser.number()?.to_radians();
+                let large_arc = parser.flag()?;
+                let sweep = parser.flag()?;
+                let end = base + parser.point()?;
+                current.extend(arc(pen, end, radii, rotation, large_arc, sweep));
+                pen = end;
+            }
+            b'Z' => {
+                current.push(start);
+                finish(&mut polylines, &mut current);
+                pen = start;
+                current.push(pen);
+                command = None;
+                closed = true;
+            }
+            _ => return Err(parser.error(&format!("unsupported command '{}'", c as char))),
+        }
+
+        if !matches!(upper, b'C' | b'S' | b'Q' | b'T') {
+            last_control = None;
+        }
+    }
+
+    finish(&mut polylines, &mut current);
+
+    Ok(polylines)
+}
+
+// ends the current subpath, keeping it only if it goes somewhere: a lone
+// moveto, or "M 0 0 Z", is a single point and not a line
+fn finish(polylines: &mut Vec<Polyline>, current: &mut Polyline) {
+    let line = std::mem::take(current);
+    if line.iter().any(|p| *p != line[0]) {
+        polylines.push(line);
+    }
+}
+
+fn reflect(last: Option<(u8, Vec2)>, kind: u8, pen: Vec2) -> Vec2 {
+    match last {
+        Some((k, control)) if k == kind => pen * 2.0 - control,
+        _ => pen,
+    }
+}
+
+// elliptical arc flattened into points (excluding the start point)
+// https://www.w3.org/TR/SVG/implnote.html#ArcConversionEndpointToCenter
+fn arc(from: Vec2, to: Vec2, radii: Vec2, rotation: f32, large_arc: bool, sweep: bool) -> Polyline {
+    let (mut rx, mut ry) = (radii.x.abs(), radii.y.abs());
+    if rx == 0.0 || ry == 0.0 || from == to {
+        return vec![to];
+    }
+
+    let (sin, cos) = rotation.sin_cos();
+    let half = (from - to) / 2.0;
+    let p = Vec2::new(cos * half.x + sin * half.y, -sin * half.x + cos * half.y);
+
+    // scale radii up if they are too small to reach the end point
+    let lambda = (p.x * p.x) / (rx * rx) + (p.y * p.y) / (ry * ry);
+    if lambda > 1.0 {
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+
+    let num = rx * rx * ry * ry - rx * rx * p.y * p.y - ry * ry * p.x * p.x;
+    let den = rx * rx * p.y * p.y + ry * ry * p.x * p.x;
+    let mut factor = (num / den).max(0.0).sqrt();
+    if large_arc == sweep {
+        factor = -factor;
+    }
+    let c = Vec2::new(factor * rx * p.y / ry, -factor * ry * p.x / rx);
+    let mid = (from + to) / 2.0;
+    let center = Vec2::new(cos * c.x - sin * c.y, sin * c.x + cos * c.y) + mid;
+
+    let angle = |v: Vec2| v.y.atan2(v.x);
+    let theta = angle(Vec2::new((p.x - c.x) / rx, (p.y - c.y) / ry));
+    let mut delta = angle(Vec2::new((-p.x - c.x) / rx, (-p.y - c.y) / ry)) - theta;
+    if sweep && delta < 0.0 {
+        delta += TAU;
+    } else if !sweep && delta > 0.0 {
+        delta -= TAU;
+    }
+
+    (1..=CURVE_SEGMENTS)
+        .map(|n| {
+            let a = theta + delta * n as f32 / CURVE_SEGMENTS as f32;
+            let (x, y) = (rx * a.cos(), ry * a.sin());
+            Vec2::new(cos * x - sin * y, sin * x + cos * y) + center
+        })
+        .collect()
+}
+
+struct PathParser<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PathParser<'a> {
+    fn done(&self) -> bool {
+        self.pos >= self.src.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.get(self.pos).copied()
+    }
+
+    fn skip_separators(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_ascii_whitespace() || c == b',') {
+            self.pos += 1;
+        }
+    }
+
+    fn command(&mut self) -> Option<u8> {
+        match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() && c != b'e' && c != b'E' => {
+                self.pos += 1;
+                Some(c)
+            }
+            _ => None,
+        }
+    }
+
+    fn number(&mut self) -> Result<f32, SvgError> {
+        self.skip_separators();
+        let start = self.pos;
+        if matches!(self.peek(), Some(b'+' | b'-')) {
+            self.pos += 1;
+        }
+        let mut seen_dot = false;
+        let mut seen_exp = false;
+        while let Some(c) = self.peek() {
+            match c {
+                b'0'..=b'9' => {}
+                b'.' if !seen_dot && !seen_exp => seen_dot = true,
+                b'e' | b'E' if !seen_exp => {
+                    seen_exp = true;
+                    if matches!(self.src.get(self.pos + 1), Some(b'+' | b'-')) {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+            self.pos += 1;
+        }
+
+        std::str::from_utf8(&self.src[start..self.pos])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| SvgError::Parse {
+                position: start,
+                message: "expected a number".to_string(),
+            })
+    }
+
+    fn point(&mut self) -> Result<Vec2, SvgError> {
+        Ok(Vec2::new(self.number()?, self.number()?))
+    }
+
+    // arc flags may be written without separators, e.g. "a1 1 0 01 1 1"
+    fn flag(&mut self) -> Result<bool, SvgError> {
+        self.skip_separators();
+        match self.peek() {
+            Some(b'0') => {
+                self.pos += 1;
+                Ok(false)
+            }
+            Some(b'1') => {
+                self.pos += 1;
+                Ok(true)
+            }
+            _ => Err(self.error("expected an arc flag (0 or 1)")),
+        }
+    }
+
+    fn error(&self, message: &str) -> SvgError {
+        SvgError::Parse {
+            position: self.pos,
+            message: message.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(d: &str) -> String {
+        match parse_path(d) {
+            Err(SvgError::Parse { message, .. }) => message,
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn closed_subpaths() {
+        let lines = parse_path("M 0 0 L 10 0 L 10 10 Z m 5 5 h 1").unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].first(), lines[0].last());
+        assert_eq!(lines[1], vec![Vec2::new(5.0, 5.0), Vec2::new(6.0, 5.0)]);
+    }
+
+    #[test]
+    fn single_point_subpaths_are_dropped() {
+        assert!(parse_path("M 0 0 Z").unwrap().is_empty());
+        assert!(parse_path("M 1 1 M 2 2").unwrap().is_empty());
+        assert_eq!(parse_path("M 0 0 Z M 1 1 L 2 2").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn missing_commands() {
+        assert_eq!(message("0 0 L 1 1"), "path data must start with a command");
+        assert_eq!(message("M 0 0 L 1 1 Z 2 2"), "expected a command after Z");
+    }
+}
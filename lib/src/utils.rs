@@ -1,3 +1,4 @@
+use nannou::noise::{Fbm, MultiFractal, Seedable};
 use nannou::rand::rngs::StdRng;
 use nannou::rand::Rng;
 use nannou::rand::SeedableRng;
@@ -6,6 +7,14 @@ pub fn rng(seed: u64) -> StdRng {
     StdRng::seed_from_u64(seed)
 }
 
+// noise 0.7 glob-exports two structs named `Perlin`, which rustc rejects as ambiguous;
+// a single octave `Fbm` is the same Perlin noise, value for value, under a name of its own
+pub type PerlinNoise = Fbm;
+
+pub fn perlin(seed: u32) -> PerlinNoise {
+    Fbm::new().set_octaves(1).set_seed(seed)
+}
+
 // random number between min and max with exponential distribution
 // k is the exponential distribution parameter
 // favor_min will favor min if true, max if false
@@ -41,3 +50,82 @@ pub fn wave_d(x: f32, d: f32) -> f32 {
 pub fn wave_r(x: f32) -> f32 {
     wave(x, 0.5, 0.5, 0.5, 0.5)
 }
+
+// integer hash of a 2D lattice point and a seed
+// same (i, j, seed) always gives the same value, independent of call order,
+// which makes it a drop-in replacement for global rng calls inside fields
+// https://nullprogram.com/blog/2018/07/31/ (lowbias32)
+pub fn hash2(i: i64, j: i64, seed: u32) -> u32 {
+    let mut h = seed ^ 0x9e37_79b9;
+    h = mix32(h ^ (i as u32));
+    h = mix32(h ^ ((i >> 32) as u32));
+    h = mix32(h ^ (j as u32));
+    h = mix32(h ^ ((j >> 32) as u32));
+    h
+}
+
+// hash of a 2D lattice point mapped to [0, 1)
+pub fn hash2_unit(i: i64, j: i64, seed: u32) -> f32 {
+    (hash2(i, j, seed) >> 8) as f32 / (1u32 << 24) as f32
+}
+
+fn mix32(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+// separable gaussian blur of a row-major w x h buffer
+// radius is in cells, sigma is radius / 2; a radius below 0.5 returns the buffer as is
+pub fn blur(values: &[f32], w: usize, h: usize, radius: f32) -> Vec<f32> {
+    if radius < 0.5 {
+        return values.to_vec();
+    }
+
+    let sigma = radius / 2.0;
+    let reach = radius.ceil() as isize;
+    let kernel: Vec<f32> = (-reach..=reach)
+        .map(|k| (-(k * k) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    convolve(values, w, h, &kernel)
+}
+
+// separable box blur of a row-major w x h buffer, averaging `radius` cells on each side
+pub fn box_blur(values: &[f32], w: usize, h: usize, radius: usize) -> Vec<f32> {
+    convolve(values, w, h, &vec![1.0; 2 * radius + 1])
+}
+
+// a symmetric kernel run along rows then columns, clamping at the borders
+fn convolve(values: &[f32], w: usize, h: usize, kernel: &[f32]) -> Vec<f32> {
+    if w == 0 || h == 0 || kernel.len() < 2 {
+        return values.to_vec();
+    }
+
+    let reach = (kernel.len() / 2) as isize;
+    let total: f32 = kernel.iter().sum();
+
+    let pass = |src: &[f32], horizontal: bool| -> Vec<f32> {
+        let mut out = vec![0.0; src.len()];
+        for y in 0..h {
+            for x in 0..w {
+                let mut acc = 0.0;
+                for (n, k) in kernel.iter().enumerate() {
+                    let offset = n as isize - reach;
+                    let (sx, sy) = if horizontal {
+                        ((x as isize + offset).clamp(0, w as isize - 1) as usize, y)
+                    } else {
+                        (x, (y as isize + offset).clamp(0, h as isize - 1) as usize)
+                    };
+                    acc += src[sy * w + sx] * k;
+                }
+                out[y * w + x] = acc / total;
+            }
+        }
+        out
+    };
+
+    pass(&pass(values, true), false)
+}
@@ -0,0 +1,169 @@
+use crate::verlet::point::VerletPoint;
+use nannou::glam::Vec2;
+use nannou::prelude::{PI, TAU};
+
+// Links between points, by index into the world's points
+//
+// Distance : keeps a and b `length` apart, `stiffness` in [0, 1] is the share of the error
+//            corrected per iteration (1 is rigid, lower values stretch like cloth)
+// Spring   : pulls a and b towards `rest` apart with a force, Hooke's `k` plus `damping`
+//            of their relative speed; springy, unlike the distance constraint
+// Pin      : holds a point at a position that can be moved, e.g. by the mouse; the other
+//            constraints treat it as pinned
+// Angle    : keeps the angle a-b-c (at b, radians, counter-clockwise from a to c)
+//            by rotating a and c around b
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Constraint {
+    Distance {
+        a: usize,
+        b: usize,
+        length: f32,
+        stiffness: f32,
+    },
+    Spring {
+        a: usize,
+        b: usize,
+        rest: f32,
+        k: f32,
+        damping: f32,
+    },
+    Pin {
+        point: usize,
+        position: Vec2,
+    },
+    Angle {
+        a: usize,
+        b: usize,
+        c: usize,
+        angle: f32,
+        stiffness: f32,
+    },
+}
+
+impl Constraint {
+    // spring forces, applied before the points are integrated
+    pub(crate) fn apply_force(&self, points: &mut [VerletPoint], dt: f32) {
+        if let Constraint::Spring {
+            a,
+            b,
+            rest,
+            k,
+            damping,
+        } = *self
+        {
+            let delta = points[b].position - points[a].position;
+            let direction = delta.normalize_or_zero();
+            let stretch = delta.length() - rest;
+            let closing = (points[b].velocity(dt) - points[a].velocity(dt)).dot(direction);
+            let force = direction * (k * stretch + damping * closing);
+            points[a].apply_force(force);
+            points[b].apply_force(-force);
+        }
+    }
+
+    // moves the points towards satisfying the constraint, once per iteration;
+    // `held` points (pinned, or held by a `Pin`) are not moved by the other constraints
+    pub(crate) fn relax(&self, points: &mut [VerletPoint], held: &[bool]) {
+        let weight = |points: &[VerletPoint], n: usize| {
+            if held[n] {
+                0.0
+            } else {
+                points[n].inverse_mass()
+            }
+        };
+        match *self {
+            Constraint::Distance {
+                a,
+                b,
+                length,
+                stiffness,
+            } => {
+                let (wa, wb) = (weight(points, a), weight(points, b));
+                if wa + wb <= 0.0 {
+                    return;
+                }
+                let delta = points[b].position - points[a].position;
+                let d = delta.length();
+                if d <= f32::EPSILON {
+                    return;
+                }
+                let correction = delta * ((d - length) / d) * stiffness / (wa + wb);
+                points[a].position += correction * wa;
+                points[b].position -= correction * wb;
+            }
+            Constraint::Pin { point, position } => {
+                points[point].position = position;
+            }
+            Constraint::Angle {
+                a,
+                b,
+                c,
+                angle,
+                stiffness,
+            } => {
+                let pivot = points[b].position;
+                let (ba, bc) = (points[a].position - pivot, points[c].position - pivot);
+                if ba == Vec2::ZERO || bc == Vec2::ZERO {
+                    return;
+                }
+                let current = ba.perp_dot(bc).atan2(ba.dot(bc));
+                let error = (current - angle + PI).rem_euclid(TAU) - PI;
+
+                // a turns towards c and c towards a, shared by how free they are to move
+                let (wa, wc) = (weight(points, a), weight(points, c));
+                if wa + wc <= 0.0 {
+                    return;
+                }
+                let turn = error * stiffness / (wa + wc);
+                points[a].position = pivot + rotate(ba, turn * wa);
+                points[c].position = pivot + rotate(bc, -turn * wc);
+            }
+            Constraint::Spring { .. } => (),
+        }
+    }
+}
+
+// Solid shapes points cannot enter, they are pushed out to the nearest edge
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Collider {
+    Circle { center: Vec2, radius: f32 },
+    Rect { min: Vec2, max: Vec2 },
+}
+
+impl Collider {
+    pub(crate) fn push_out(&self, point: &mut VerletPoint) {
+        if point.pinned {
+            return;
+        }
+        let p = point.position;
+        match *self {
+            Collider::Circle { center, radius } => {
+                let offset = p - center;
+                if offset.length_squared() < radius * radius {
+                    let mut direction = offset.normalize_or_zero();
+                    if direction == Vec2::ZERO {
+                        direction = Vec2::Y;
+                    }
+                    point.position = center + direction * radius;
+                }
+            }
+            Collider::Rect { min, max } => {
+                if p.cmpgt(min).all() && p.cmplt(max).all() {
+                    let edges = [
+                        (p.x - min.x, Vec2::new(min.x, p.y)),
+                        (max.x - p.x, Vec2::new(max.x, p.y)),
+                        (p.y - min.y, Vec2::new(p.x, min.y)),
+                        (max.y - p.y, Vec2::new(p.x, max.y)),
+                    ];
+                    let nearest = edges.iter().min_by(|a, b| a.0.total_cmp(&b.0)).unwrap();
+                    point.position = nearest.1;
+                }
+            }
+        }
+    }
+}
+
+fn rotate(v: Vec2, angle: f32) -> Vec2 {
+    let (sin, cos) = angle.sin_cos();
+    Vec2::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos)
+}
@@ -0,0 +1,3 @@
+pub mod constraint;
+pub mod point;
+pub mod world;
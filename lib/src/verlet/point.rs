@@ -0,0 +1,74 @@
+use nannou::glam::Vec2;
+
+// A point mass integrated with position Verlet: the velocity is implied by where the
+// point was on the previous step, so constraints only ever move positions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VerletPoint {
+    pub position: Vec2,
+    pub previous: Vec2,
+    // forces applied since the last step, divided by the mass; cleared every step
+    pub acceleration: Vec2,
+    pub mass: f32,
+    // pinned points do not move, whatever pulls on them
+    pub pinned: bool,
+}
+
+impl VerletPoint {
+    pub fn new(position: Vec2) -> Self {
+        VerletPoint {
+            position,
+            previous: position,
+            acceleration: Vec2::ZERO,
+            mass: 1.0,
+            pinned: false,
+        }
+    }
+
+    pub fn mass(&mut self, mass: f32) -> &mut Self {
+        self.mass = mass.max(f32::EPSILON);
+        self
+    }
+
+    pub fn pin(&mut self, pinned: bool) -> &mut Self {
+        self.pinned = pinned;
+        self
+    }
+
+    pub fn apply_force(&mut self, force: Vec2) -> &mut Self {
+        self.acceleration += force / self.mass;
+        self
+    }
+
+    // moves the point and its previous position alike, keeping its velocity
+    pub fn move_to(&mut self, position: Vec2) -> &mut Self {
+        self.previous += position - self.position;
+        self.position = position;
+        self
+    }
+
+    // velocity over the last step of dt seconds
+    pub fn velocity(&self, dt: f32) -> Vec2 {
+        (self.position - self.previous) / dt.max(f32::EPSILON)
+    }
+
+    // how easily constraints move the point, 0 when pinned
+    pub fn inverse_mass(&self) -> f32 {
+        if self.pinned {
+            0.0
+        } else {
+            1.0 / self.mass
+        }
+    }
+
+    // one step of dt seconds, losing `damping` of the velocity
+    pub fn step(&mut self, dt: f32, damping: f32) {
+        if self.pinned {
+            self.previous = self.position;
+        } else {
+            let velocity = (self.position - self.previous) * (1.0 - damping);
+            self.previous = self.position;
+            self.position += velocity + self.acceleration * dt * dt;
+        }
+        self.acceleration = Vec2::ZERO;
+    }
+}
@@ -0,0 +1,371 @@
+use crate::particles::system::ParticleForce;
+use crate::verlet::constraint::{Collider, Constraint};
+use crate::verlet::point::VerletPoint;
+use nannou::color::Rgba;
+use nannou::glam::Vec2;
+use nannou::Draw;
+
+// Verlet points held together by constraints, stepped with a fixed timestep like
+// `ParticleSystem`. Every step integrates the points under gravity, springs and an external
+// force, then relaxes the constraints, colliders and bounds `iterations` times; more
+// iterations make chains and cloth stiffer.
+//
+// External forces are anything `ParticleForce`: a baked `FlowField`, a closure, or a raw
+// `ForceField` through `FieldForce`, e.g. perlin noise as wind:
+//
+//     let mut world = World::new(1.0 / 120.0);
+//     let top = world.chain(vec2(100.0, 50.0), vec2(700.0, 50.0), 40, 1.0);
+//     world.pin(top[0]).pin(top[40]).gravity(vec2(0.0, 400.0));
+//     let wind = FieldForce::with_resolution(&perlin, 20.0);
+//     world.update(update.since_last.as_secs_f32(), &wind);
+pub struct World {
+    points: Vec<VerletPoint>,
+    constraints: Vec<Constraint>,
+    colliders: Vec<Collider>,
+    timestep: f32,
+    accumulator: f32,
+    time: f64,
+    iterations: usize,
+    gravity: Vec2,
+    drag: f32,
+    bounds: Option<[Vec2; 2]>,
+}
+
+impl World {
+    pub const DEFAULT_ITERATIONS: usize = 8;
+    // steps behind are dropped beyond this, so a long frame does not freeze the sketch
+    pub const MAX_STEPS_PER_UPDATE: usize = 32;
+
+    pub fn new(timestep: f32) -> Self {
+        World {
+            points: Vec::new(),
+            constraints: Vec::new(),
+            colliders: Vec::new(),
+            timestep: timestep.max(f32::EPSILON),
+            accumulator: 0.0,
+            time: 0.0,
+            iterations: Self::DEFAULT_ITERATIONS,
+            gravity: Vec2::ZERO,
+            drag: 0.0,
+            bounds: None,
+        }
+    }
+
+    pub fn iterations(&mut self, iterations: usize) -> &mut Self {
+        self.iterations = iterations.max(1);
+        self
+    }
+
+    // acceleration on every point, whatever its mass
+    pub fn gravity(&mut self, gravity: Vec2) -> &mut Self {
+        self.gravity = gravity;
+        self
+    }
+
+    // fraction of the velocity lost per second
+    pub fn drag(&mut self, drag: f32) -> &mut Self {
+        self.drag = drag.max(0.0);
+        self
+    }
+
+    // keeps every point inside a rectangle
+    pub fn bounds(&mut self, min: Vec2, max: Vec2) -> &mut Self {
+        self.bounds = Some([min.min(max), min.max(max)]);
+        self
+    }
+
+    // adds a point, returns its index
+    pub fn add(&mut self, point: VerletPoint) -> usize {
+        self.points.push(point);
+        self.points.len() - 1
+    }
+
+    pub fn point(&mut self, position: Vec2) -> usize {
+        self.add(VerletPoint::new(position))
+    }
+
+    pub fn constrain(&mut self, constraint: Constraint) -> &mut Self {
+        self.constraints.push(constraint);
+        self
+    }
+
+    pub fn collider(&mut self, collider: Collider) -> &mut Self {
+        self.colliders.push(collider);
+        self
+    }
+
+    // keeps a and b as far apart as they are now
+    pub fn distance(&mut self, a: usize, b: usize, stiffness: f32) -> &mut Self {
+        let length = self.points[a].position.distance(self.points[b].position);
+        self.constrain(Constraint::Distance {
+            a,
+            b,
+            length,
+            stiffness: stiffness.clamp(0.0, 1.0),
+        })
+    }
+
+    // a spring at rest at the current distance between a and b
+    pub fn spring(&mut self, a: usize, b: usize, k: f32, damping: f32) -> &mut Self {
+        let rest = self.points[a].position.distance(self.points[b].position);
+        self.constrain(Constraint::Spring {
+            a,
+            b,
+            rest,
+            k,
+            damping,
+        })
+    }
+
+    // keeps the angle a-b-c at what it is now
+    pub fn angle(&mut self, a: usize, b: usize, c: usize, stiffness: f32) -> &mut Self {
+        let pivot = self.points[b].position;
+        let (ba, bc) = (
+            self.points[a].position - pivot,
+            self.points[c].position - pivot,
+        );
+        self.constrain(Constraint::Angle {
+            a,
+            b,
+            c,
+            angle: ba.perp_dot(bc).atan2(ba.dot(bc)),
+            stiffness: stiffness.clamp(0.0, 1.0),
+        })
+    }
+
+    // fixes a point where it is
+    pub fn pin(&mut self, point: usize) -> &mut Self {
+        self.points[point].pin(true);
+        self
+    }
+
+    // `segments` + 1 points from `from` to `to`, each linked to the next
+    pub fn chain(&mut self, from: Vec2, to: Vec2, segments: usize, stiffness: f32) -> Vec<usize> {
+        let segments = segments.max(1);
+        let points: Vec<usize> = (0..=segments)
+            .map(|n| self.point(from.lerp(to, n as f32 / segments as f32)))
+            .collect();
+        for pair in points.windows(2) {
+            self.distance(pair[0], pair[1], stiffness);
+        }
+        points
+    }
+
+    // a grid of `cols` x `rows` points `spacing` apart from `origin` (x right, y down),
+    // linked to their right and lower neighbours; row-major indices
+    pub fn cloth(
+        &mut self,
+        origin: Vec2,
+        cols: usize,
+        rows: usize,
+        spacing: f32,
+        stiffness: f32,
+    ) -> Vec<usize> {
+        let (cols, rows) = (cols.max(1), rows.max(1));
+        let points: Vec<usize> = (0..rows)
+            .flat_map(|j| (0..cols).map(move |i| (i, j)))
+            .map(|(i, j)| self.point(origin + Vec2::new(i as f32, j as f32) * spacing))
+            .collect();
+        for j in 0..rows {
+            for i in 0..cols {
+                let n = j * cols + i;
+                if i + 1 < cols {
+                    self.distance(points[n], points[n + 1], stiffness);
+                }
+                if j + 1 < rows {
+                    self.distance(points[n], points[n + cols], stiffness);
+                }
+            }
+        }
+        points
+    }
+
+    pub fn points(&self) -> &[VerletPoint] {
+        &self.points
+    }
+
+    pub fn points_mut(&mut self) -> &mut Vec<VerletPoint> {
+        &mut self.points
+    }
+
+    pub fn constraints(&self) -> &[Constraint] {
+        &self.constraints
+    }
+
+    pub fn constraints_mut(&mut self) -> &mut Vec<Constraint> {
+        &mut self.constraints
+    }
+
+    pub fn colliders_mut(&mut self) -> &mut Vec<Collider> {
+        &mut self.colliders
+    }
+
+    // seconds simulated so far
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn timestep(&self) -> f32 {
+        self.timestep
+    }
+
+    // advance by `elapsed` seconds in fixed steps, returns how many steps were run
+    pub fn update(&mut self, elapsed: f32, force: &impl ParticleForce) -> usize {
+        self.accumulator += elapsed.max(0.0);
+        let mut steps = 0;
+        while self.accumulator >= self.timestep {
+            self.accumulator -= self.timestep;
+            if steps < Self::MAX_STEPS_PER_UPDATE {
+                self.step(force);
+                steps += 1;
+            }
+        }
+        steps
+    }
+
+    // a single step of `timestep` seconds
+    pub fn step(&mut self, force: &impl ParticleForce) -> &mut Self {
+        let dt = self.timestep;
+        let damping = (self.drag * dt).min(1.0);
+
+        for constraint in &self.constraints {
+            constraint.apply_force(&mut self.points, dt);
+        }
+        for point in self.points.iter_mut() {
+            let f = force.force_at(point.position, self.time);
+            point.apply_force(f);
+            point.acceleration += self.gravity;
+            point.step(dt, damping);
+        }
+
+        let held = self.held();
+        for _ in 0..self.iterations {
+            for constraint in &self.constraints {
+                constraint.relax(&mut self.points, &held);
+            }
+            for (point, held) in self.points.iter_mut().zip(&held) {
+                if *held {
+                    continue;
+                }
+                self.colliders.iter().for_each(|c| c.push_out(point));
+                if let Some([min, max]) = self.bounds {
+                    point.position = point.position.clamp(min, max);
+                }
+            }
+        }
+
+        self.time += dt as f64;
+        self
+    }
+
+    // points that nothing but their pin moves: pinned ones and those held by a `Pin`
+    fn held(&self) -> Vec<bool> {
+        let mut held: Vec<bool> = self.points.iter().map(|p| p.pinned).collect();
+        for constraint in &self.constraints {
+            if let Constraint::Pin { point, .. } = *constraint {
+                held[point] = true;
+            }
+        }
+        held
+    }
+
+    // distance constraints and springs as lines, pinned and `Pin` held points as dots
+    pub fn display(&self, draw: &Draw, color: Option<Rgba>) {
+        let color: Rgba = color.unwrap_or(Rgba::new(0.0, 0.0, 0.0, 0.8));
+        for constraint in &self.constraints {
+            if let Constraint::Distance { a, b, .. } | Constraint::Spring { a, b, .. } = *constraint
+            {
+                draw.line()
+                    .start(self.points[a].position)
+                    .end(self.points[b].position)
+                    .weight(1.0)
+                    .color(color);
+            }
+        }
+        for (point, _) in self.points.iter().zip(self.held()).filter(|(_, h)| *h) {
+            draw.ellipse().xy(point.position).radius(3.0).color(color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_fall_under_gravity() {
+        let mut world = World::new(0.01);
+        let p = world.point(Vec2::ZERO);
+        world.gravity(Vec2::new(0.0, 10.0));
+        for _ in 0..100 {
+            world.step(&());
+        }
+        // about g t² / 2 after a second
+        let y = world.points()[p].position.y;
+        assert!((y - 5.0).abs() < 0.2, "{}", y);
+    }
+
+    #[test]
+    fn pinned_chain_hangs_without_stretching() {
+        let mut world = World::new(1.0 / 120.0);
+        let chain = world.chain(Vec2::ZERO, Vec2::new(100.0, 0.0), 10, 1.0);
+        world
+            .pin(chain[0])
+            .pin(chain[10])
+            .iterations(40)
+            .gravity(Vec2::new(0.0, 400.0));
+        for _ in 0..240 {
+            world.step(&());
+        }
+
+        let points = world.points();
+        assert_eq!(points[chain[0]].position, Vec2::ZERO);
+        assert_eq!(points[chain[10]].position, Vec2::new(100.0, 0.0));
+        // the middle sags, but every link keeps close to its length
+        assert!(points[chain[5]].position.y > 1.0);
+        for pair in chain.windows(2) {
+            let length = points[pair[0]].position.distance(points[pair[1]].position);
+            assert!((length - 10.0).abs() < 0.5, "{}", length);
+        }
+    }
+
+    #[test]
+    fn pin_constraints_are_not_pulled_by_other_constraints() {
+        let mut world = World::new(1.0 / 120.0);
+        let chain = world.chain(Vec2::ZERO, Vec2::new(50.0, 0.0), 5, 1.0);
+        let anchor = Vec2::new(-20.0, 0.0);
+        // the pin comes first, so any later constraint would move the point off it
+        world.constraints_mut().insert(
+            0,
+            Constraint::Pin {
+                point: chain[0],
+                position: anchor,
+            },
+        );
+        world.gravity(Vec2::new(0.0, 400.0));
+        for _ in 0..120 {
+            world.step(&());
+        }
+        assert_eq!(world.points()[chain[0]].position, anchor);
+    }
+
+    #[test]
+    fn colliders_and_bounds_push_points_out() {
+        let mut world = World::new(0.01);
+        let p = world.point(Vec2::new(0.0, -20.0));
+        world
+            .gravity(Vec2::new(0.0, 1000.0))
+            .collider(Collider::Circle {
+                center: Vec2::ZERO,
+                radius: 10.0,
+            })
+            .bounds(Vec2::splat(-50.0), Vec2::splat(50.0));
+        let q = world.point(Vec2::new(30.0, 0.0));
+        for _ in 0..200 {
+            world.step(&());
+        }
+        // one rests on top of the circle, the other on the floor
+        assert!(world.points()[p].position.length() >= 10.0 - 1e-3);
+        assert!((world.points()[q].position.y - 50.0).abs() < 1e-3);
+    }
+}
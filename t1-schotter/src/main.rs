@@ -147,24 +147,19 @@ fn key_pressed(app: &App, model: &mut Model, key: Key) {
                 model.cur_frame = 0;
             }
         }
-        Key::S => match app.window(model.main_window) {
-            Some(window) => {
+        Key::S => {
+            if let Some(window) = app.window(model.main_window) {
                 window.capture_frame(format!("generated/{}.png", app.exe_name().unwrap()));
             }
-            None => {}
-        },
+        }
         Key::Up => {
             model.disp_adj += 0.1;
         }
-        Key::Down => {
-            if model.disp_adj > 0.0 {
-                model.disp_adj -= 0.1;
-            }
+        Key::Down if model.disp_adj > 0.0 => {
+            model.disp_adj -= 0.1;
         }
-        Key::Left => {
-            if model.rot_adj > 0.0 {
-                model.rot_adj -= 0.1;
-            }
+        Key::Left if model.rot_adj > 0.0 => {
+            model.rot_adj -= 0.1;
         }
         Key::Right => {
             model.rot_adj += 0.1;
@@ -205,7 +200,7 @@ fn update(app: &App, model: &mut Model, _update: Update) {
         }
     }
 
-    if model.recording && app.elapsed_frames() % 2 == 0 {
+    if model.recording && app.elapsed_frames().is_multiple_of(2) {
         model.cur_frame += 1;
         if model.cur_frame > 9999 {
             model.recording = false;
@@ -214,11 +209,8 @@ fn update(app: &App, model: &mut Model, _update: Update) {
                 "generated/{}/schotter{:>04}.png",
                 model.frames_dir, model.cur_frame
             );
-            match app.window(model.main_window) {
-                Some(window) => {
-                    window.capture_frame(filename);
-                }
-                None => {}
+            if let Some(window) = app.window(model.main_window) {
+                window.capture_frame(filename);
             }
         }
     }